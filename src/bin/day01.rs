@@ -1,36 +1,117 @@
-use anyhow::bail;
+use anyhow::{anyhow, bail};
 use aoc::{Parse, Result, Solve};
-use std::collections::HashSet;
+use itertools::Itertools;
 
+/// The entries of an expense report, sorted, keeping any duplicates.
 #[derive(Debug)]
 struct ExpenseReport {
-    entries: HashSet<i32>,
+    entries: Vec<i64>,
 }
 
 impl<'a> Parse<'a> for ExpenseReport {
     fn parse<'b: 'a>(input: &'b str) -> Result<Self> {
         let lines = input.lines().map(str::trim);
-        let entries = lines.map(str::parse).collect::<Result<_, _>>()?;
+        let mut entries = lines.map(str::parse).collect::<Result<Vec<_>, _>>()?;
+        entries.sort_unstable();
         Ok(ExpenseReport { entries })
     }
 }
 
-const TARGET: i32 = 2020;
+impl ExpenseReport {
+    /// Finds `k` entries that sum to `target`, returned in ascending order.
+    /// An entry may appear in the result as many times as it is in the report.
+    ///
+    /// Fixes the smallest entry and recurses until two are left, which are
+    /// found by moving in from both ends of the sorted entries, so this takes
+    /// O(n^(k-1)) time.
+    fn k_sum(&self, k: usize, target: i64) -> Option<Vec<i64>> {
+        if k > self.entries.len() {
+            return None;
+        }
+        let mut combination = Vec::with_capacity(k);
+        if k_sum(&self.entries, k, target, &mut combination) {
+            Some(combination)
+        } else {
+            None
+        }
+    }
+
+    /// Finds `k` entries that sum to `target`, and returns their product.
+    fn product(&self, k: usize, target: i64) -> Result<i64> {
+        let combination = self
+            .k_sum(k, target)
+            .ok_or_else(|| anyhow!("no {} entries sum to {}", k, target))?;
+        combination
+            .iter()
+            .try_fold(1i64, |product, &entry| product.checked_mul(entry))
+            .ok_or_else(|| anyhow!("product of {:?} overflows", combination))
+    }
+}
+
+/// Pushes `k` entries of the sorted slice `entries` that sum to `target`
+/// onto `combination`, returning false and leaving it as it was if there
+/// are none.
+fn k_sum(entries: &[i64], k: usize, target: i64, combination: &mut Vec<i64>) -> bool {
+    match k {
+        0 => return target == 0,
+        1 => {
+            let found = entries.binary_search(&target).is_ok();
+            if found {
+                combination.push(target);
+            }
+            return found;
+        }
+        2 => {
+            if entries.len() < 2 {
+                return false;
+            }
+            let (mut lo, mut hi) = (0, entries.len() - 1);
+            while lo < hi {
+                let sum = i128::from(entries[lo]) + i128::from(entries[hi]);
+                match sum.cmp(&i128::from(target)) {
+                    std::cmp::Ordering::Less => lo += 1,
+                    std::cmp::Ordering::Greater => hi -= 1,
+                    std::cmp::Ordering::Equal => {
+                        combination.extend(&[entries[lo], entries[hi]]);
+                        return true;
+                    }
+                }
+            }
+            return false;
+        }
+        _ => {}
+    }
+    for (i, &entry) in entries.iter().enumerate() {
+        // Every later entry is at least this one, so the sum can only grow.
+        if entries.len() - i < k || i128::from(entry) * k as i128 > i128::from(target) {
+            break;
+        }
+        if i > 0 && entries[i - 1] == entry {
+            continue;
+        }
+        let rest = match target.checked_sub(entry) {
+            Some(rest) => rest,
+            None => continue,
+        };
+        combination.push(entry);
+        if k_sum(&entries[i + 1..], k - 1, rest, combination) {
+            return true;
+        }
+        combination.pop();
+    }
+    false
+}
+
+const TARGET: i64 = 2020;
 
 struct PartOne;
 
 impl Solve<'_> for PartOne {
     type Input = ExpenseReport;
-    type Solution = i32;
+    type Solution = i64;
 
     fn solve(input: &Self::Input) -> Result<Self::Solution> {
-        for x in input.entries.iter() {
-            let y = TARGET - x;
-            if input.entries.contains(&y) {
-                return Ok(x * y);
-            }
-        }
-        bail!("no two numbers found");
+        input.product(2, TARGET)
     }
 }
 
@@ -38,22 +119,34 @@ struct PartTwo;
 
 impl Solve<'_> for PartTwo {
     type Input = ExpenseReport;
-    type Solution = i32;
+    type Solution = i64;
 
     fn solve(input: &Self::Input) -> Result<Self::Solution> {
-        for x in input.entries.iter() {
-            for y in input.entries.iter() {
-                let z = TARGET - x - y;
-                if input.entries.contains(&z) {
-                    return Ok(x * y * z);
-                }
-            }
+        input.product(3, TARGET)
+    }
+}
+
+const USAGE: &str = "usage: day01 K [TARGET]";
+
+/// Finds any number of entries summing to a target, e.g. `day01 4 2020`.
+fn cli(input_str: &str, args: &[String]) -> Result<()> {
+    let report = ExpenseReport::parse(input_str)?;
+    let (k, target) = match args {
+        [k] => (k.parse()?, TARGET),
+        [k, target] => (k.parse()?, target.parse()?),
+        _ => bail!("{}", USAGE),
+    };
+    match report.k_sum(k, target) {
+        Some(combination) => {
+            println!("{} = {}", combination.iter().join(" + "), target);
+            println!("[day01] product is {}", report.product(k, target)?);
         }
-        bail!("no three numbers found");
+        None => println!("[day01] no {} entries sum to {}", k, target),
     }
+    Ok(())
 }
 
-aoc::main!(day01);
+aoc::main!(day01, cli = cli);
 
 #[cfg(test)]
 mod examples {
@@ -74,6 +167,24 @@ mod examples {
         assert_eq!(PartOne::solve(&input).unwrap(), 1721 * 299);
         assert_eq!(PartTwo::solve(&input).unwrap(), 979 * 366 * 675);
     }
+
+    #[test]
+    fn k_sums() {
+        let input = ExpenseReport::parse("1010\n5\n1010\n-3\n7\n2").unwrap();
+        assert_eq!(input.k_sum(2, 2020), Some(vec![1010, 1010]));
+        assert_eq!(input.k_sum(3, 2020), None);
+        assert_eq!(input.k_sum(3, 4), Some(vec![-3, 2, 5]));
+        assert_eq!(input.k_sum(4, 2022), Some(vec![-3, 5, 1010, 1010]));
+        assert_eq!(input.k_sum(6, 2031), Some(vec![-3, 2, 5, 7, 1010, 1010]));
+        assert_eq!(input.k_sum(7, 2031), None);
+        assert_eq!(input.k_sum(usize::MAX, 0), None);
+        assert_eq!(input.k_sum(1, 7), Some(vec![7]));
+        assert_eq!(input.k_sum(0, 0), Some(vec![]));
+
+        let input = ExpenseReport::parse("1010\n5").unwrap();
+        assert_eq!(input.k_sum(2, 2020), None);
+        assert!(input.product(2, 2020).is_err());
+    }
 }
 
 aoc::solved!(day01, PartOne = 1018944, PartTwo = 8446464);
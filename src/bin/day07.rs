@@ -1,8 +1,9 @@
-use anyhow::bail;
-use aoc::{Parse, Result, Solve};
+use anyhow::{bail, ensure};
+use aoc::{graph::Graph, Parse, Result, Solve};
+use itertools::Itertools;
 use lazy_static::lazy_static;
 use regex::Regex;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 #[derive(Debug)]
 struct BagRule<'a> {
@@ -22,32 +23,131 @@ impl<'a> Parse<'a> for BagRule<'a> {
             (Some(color), Some(contents)) => (color, contents),
             _ => bail!("could not split line: {:?}", input_str),
         };
-        let contents = BAG_CONTENTS_REGEX
-            .captures_iter(contents)
-            .map(|caps| match (caps.name("color"), caps.name("count")) {
-                (Some(color), Some(count)) => Ok((color.as_str(), count.as_str().parse()?)),
+        let mut rule = BagRule {
+            color,
+            contents: HashMap::new(),
+        };
+        for caps in BAG_CONTENTS_REGEX.captures_iter(contents) {
+            let (inner, count) = match (caps.name("color"), caps.name("count")) {
+                (Some(inner), Some(count)) => (inner.as_str(), count.as_str().parse()?),
                 _ => bail!("failed to match count and color in captures: {:?}", caps),
-            })
-            .collect::<Result<_>>()?;
-        Ok(BagRule { color, contents })
+            };
+            ensure!(
+                rule.contents.insert(inner, count).is_none(),
+                "{:?} bags contain {:?} bags more than once",
+                color,
+                inner
+            );
+        }
+        Ok(rule)
     }
 }
 
 #[derive(Debug)]
 struct BagRules<'a> {
-    rules: HashMap<&'a str, BagRule<'a>>,
+    /// Has an edge from each bag to every bag it directly contains, weighted
+    /// by how many of that bag it contains.
+    graph: Graph<&'a str, u32>,
 }
 
 impl<'a> Parse<'a> for BagRules<'a> {
     fn parse<'b: 'a>(input_str: &'b str) -> Result<Self> {
         let lines = input_str.lines().map(str::trim);
-        let rules = lines
-            .map(|line| {
-                let rule = BagRule::parse(line)?;
-                Ok((rule.color, rule))
-            })
-            .collect::<Result<_>>()?;
-        Ok(BagRules { rules })
+        let rules = lines.map(BagRule::parse).collect::<Result<Vec<_>>>()?;
+
+        let mut graph = Graph::new();
+        for rule in &rules {
+            ensure!(
+                !graph.contains(rule.color),
+                "duplicate rule for {:?} bags",
+                rule.color
+            );
+            graph.add_node(rule.color);
+        }
+        for rule in &rules {
+            for (&inner, &count) in &rule.contents {
+                ensure!(
+                    graph.contains(inner),
+                    "{:?} bags contain {:?} bags, which have no rule",
+                    rule.color,
+                    inner
+                );
+                graph.add_edge(rule.color, inner, count);
+            }
+        }
+
+        if let Some(cycle) = graph.find_cycle() {
+            bail!("bag rules contain a cycle: {}", cycle.iter().join(" -> "));
+        }
+        Ok(BagRules { graph })
+    }
+}
+
+impl<'a> BagRules<'a> {
+    /// Returns every bag that can eventually contain a `color` bag.
+    fn containers(&self, color: &str) -> Result<HashSet<&'a str>> {
+        ensure!(self.graph.contains(color), "no rule for {:?} bags", color);
+        Ok(self.graph.ancestors(color).into_iter().copied().collect())
+    }
+
+    /// Returns the number of bags required inside a `color` bag.
+    fn total_inside(&self, color: &str) -> Result<u32> {
+        ensure!(self.graph.contains(color), "no rule for {:?} bags", color);
+        Ok(self.graph.weighted_path_count(color)? - 1)
+    }
+
+    /// Returns the bags directly inside a `color` bag, sorted by color.
+    fn contents(&self, color: &str) -> Vec<(&'a str, u32)> {
+        let mut contents = self
+            .graph
+            .successors(color)
+            .map(|(&inner, count)| (inner, count))
+            .collect::<Vec<_>>();
+        contents.sort_unstable();
+        contents
+    }
+
+    /// Returns the longest chain of bags nested inside one another, starting
+    /// from a `color` bag.
+    fn deepest_chain(&self, color: &'a str) -> Result<Vec<&'a str>> {
+        fn longest<'a>(
+            rules: &BagRules<'a>,
+            color: &'a str,
+            cache: &mut HashMap<&'a str, Vec<&'a str>>,
+        ) -> Vec<&'a str> {
+            if let Some(chain) = cache.get(color) {
+                return chain.clone();
+            }
+            let mut deepest = Vec::new();
+            for (inner, _) in rules.contents(color) {
+                let chain = longest(rules, inner, cache);
+                if chain.len() > deepest.len() {
+                    deepest = chain;
+                }
+            }
+            let chain = std::iter::once(color).chain(deepest).collect::<Vec<_>>();
+            cache.insert(color, chain.clone());
+            chain
+        }
+
+        ensure!(self.graph.contains(color), "no rule for {:?} bags", color);
+        Ok(longest(self, color, &mut HashMap::new()))
+    }
+
+    /// Draws the contents of a `color` bag as an indented tree, one bag per
+    /// line, each prefixed by how many of it its parent contains.
+    fn tree(&self, color: &str) -> Result<String> {
+        fn write_tree(rules: &BagRules, color: &str, depth: usize, out: &mut String) {
+            for (inner, count) in rules.contents(color) {
+                out.push_str(&format!("{}{} {}\n", "  ".repeat(depth), count, inner));
+                write_tree(rules, inner, depth + 1, out);
+            }
+        }
+
+        ensure!(self.graph.contains(color), "no rule for {:?} bags", color);
+        let mut out = format!("{}\n", color);
+        write_tree(self, color, 1, &mut out);
+        Ok(out)
     }
 }
 
@@ -58,28 +158,7 @@ impl<'a> Solve<'a> for PartOne {
     type Solution = usize;
 
     fn solve(input: &Self::Input) -> Result<Self::Solution> {
-        fn can_hold_gold<'a>(
-            rules: &'a HashMap<&str, BagRule>,
-            bag: &'a str,
-            cache: &mut HashMap<&'a str, bool>,
-        ) -> bool {
-            cache.get(bag).copied().unwrap_or_else(|| {
-                let mut contents = rules[bag].contents.keys();
-                let ans = contents.any(|bag| can_hold_gold(rules, bag, cache));
-                cache.insert(bag, ans);
-                ans
-            })
-        }
-
-        let mut cache = HashMap::new();
-        cache.insert("shiny gold", true);
-        let count = input
-            .rules
-            .keys()
-            .copied()
-            .filter(|bag| can_hold_gold(&input.rules, bag, &mut cache))
-            .count();
-        Ok(count - 1)
+        Ok(input.containers("shiny gold")?.len())
     }
 }
 
@@ -90,19 +169,44 @@ impl<'a> Solve<'a> for PartTwo {
     type Solution = u32;
 
     fn solve(input: &Self::Input) -> Result<Self::Solution> {
-        fn total_bags(rules: &HashMap<&str, BagRule>, bag: &str) -> u32 {
-            1 + rules[bag]
-                .contents
-                .iter()
-                .map(|(bag, count)| count * total_bags(rules, bag))
-                .sum::<u32>()
+        input.total_inside("shiny gold")
+    }
+}
+
+const USAGE: &str = "usage: day07 (containers | inside | deepest | tree) COLOR\n       day07 dot";
+
+/// Answers a single question about the bag rules, e.g. `day07 tree shiny gold`.
+fn cli(input_str: &str, args: &[String]) -> Result<()> {
+    let rules = BagRules::parse(input_str)?;
+    let color = args[1..].join(" ");
+    let color = color.as_str();
+    match (args[0].as_str(), color) {
+        ("dot", "") => print!("{}", rules.graph.to_dot("bags")),
+        (_, "") => bail!("missing bag color\n{}", USAGE),
+        ("containers", _) => {
+            let mut containers = rules.containers(color)?.into_iter().collect::<Vec<_>>();
+            containers.sort_unstable();
+            for container in &containers {
+                println!("{}", container);
+            }
+            println!("[day07] {} bags can contain {}", containers.len(), color);
+        }
+        ("inside", _) => {
+            let total = rules.total_inside(color)?;
+            println!("[day07] {} bags are inside {}", total, color);
         }
-        let count = total_bags(&input.rules, "shiny gold");
-        Ok(count - 1)
+        ("deepest", _) => {
+            let chain = rules.deepest_chain(color)?;
+            println!("{}", chain.iter().join(" > "));
+            println!("[day07] {} levels of nesting", chain.len() - 1);
+        }
+        ("tree", _) => print!("{}", rules.tree(color)?),
+        (query, _) => bail!("unknown query {:?}\n{}", query, USAGE),
     }
+    Ok(())
 }
 
-aoc::main!(day07);
+aoc::main!(day07, cli = cli);
 
 #[cfg(test)]
 mod examples {
@@ -126,6 +230,48 @@ mod examples {
 
         assert_eq!(PartOne::solve(&input).unwrap(), 4);
         assert_eq!(PartTwo::solve(&input).unwrap(), 32);
+
+        let mut containers = input
+            .containers("dark olive")
+            .unwrap()
+            .into_iter()
+            .collect::<Vec<_>>();
+        containers.sort_unstable();
+        assert_eq!(
+            containers,
+            vec![
+                "bright white",
+                "dark orange",
+                "light red",
+                "muted yellow",
+                "shiny gold"
+            ]
+        );
+        assert_eq!(input.total_inside("dark olive").unwrap(), 7);
+        assert_eq!(input.total_inside("faded blue").unwrap(), 0);
+        assert!(input.containers("no such").is_err());
+        assert_eq!(
+            input.deepest_chain("light red").unwrap(),
+            vec![
+                "light red",
+                "bright white",
+                "shiny gold",
+                "dark olive",
+                "dotted black"
+            ]
+        );
+        assert_eq!(
+            input.tree("shiny gold").unwrap(),
+            indoc! {"
+                shiny gold
+                  1 dark olive
+                    4 dotted black
+                    3 faded blue
+                  2 vibrant plum
+                    6 dotted black
+                    5 faded blue
+            "}
+        );
     }
 
     #[test]
@@ -143,6 +289,47 @@ mod examples {
 
         assert_eq!(PartTwo::solve(&input).unwrap(), 126);
     }
+
+    #[test]
+    fn invalid_rules() {
+        let err = BagRules::parse(indoc! {"
+            shiny gold bags contain 2 dark red bags.
+            dark red bags contain 1 dark blue bag.
+            dark blue bags contain 3 shiny gold bags.
+        "})
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "bag rules contain a cycle: shiny gold -> dark red -> dark blue -> shiny gold"
+        );
+
+        let err = BagRules::parse(indoc! {"
+            shiny gold bags contain 2 dark red bags.
+        "})
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "\"shiny gold\" bags contain \"dark red\" bags, which have no rule"
+        );
+
+        let err = BagRules::parse(indoc! {"
+            shiny gold bags contain 1 dark red bag, 2 dark red bags.
+            dark red bags contain no other bags.
+        "})
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "\"shiny gold\" bags contain \"dark red\" bags more than once"
+        );
+
+        let input = BagRules::parse(indoc! {"
+            shiny gold bags contain 65536 dark red bags.
+            dark red bags contain 65536 dark blue bags.
+            dark blue bags contain no other bags.
+        "})
+        .unwrap();
+        assert!(PartTwo::solve(&input).is_err());
+    }
 }
 
 aoc::solved!(day07, PartOne = 348, PartTwo = 18885);
@@ -1,120 +1,235 @@
-use anyhow::bail;
-use aoc::{Parse, Result, Solve};
-use parse_display::FromStr;
-
-#[derive(Copy, Clone, Debug, FromStr)]
-enum Instr {
-    #[display("acc {0}")]
-    Acc(isize),
-    #[display("jmp {0}")]
-    Jmp(isize),
-    #[display("nop {0}")]
-    Nop(isize),
-}
+use anyhow::{anyhow, bail};
+use aoc::{
+    console::{
+        asm,
+        cfg::ControlFlowGraph,
+        debugger::{Debugger, Stop, TraceEntry},
+        Instr, InstructionSet, Machine, Outcome, Patch, Program,
+    },
+    Parse, Result, Solve,
+};
+use std::{
+    fs,
+    io::{self, BufRead, Write},
+};
 
-#[derive(Clone, Debug)]
-struct Program {
-    instrs: Vec<Instr>,
-}
+struct PartOne;
+
+impl Solve<'_> for PartOne {
+    type Input = Program;
+    type Solution = isize;
 
-impl<'a> Parse<'a> for Program {
-    fn parse<'b: 'a>(input: &'b str) -> Result<Self> {
-        let lines = input.lines().map(str::trim);
-        let instrs = lines.map(str::parse).collect::<Result<_, _>>()?;
-        Ok(Program { instrs })
+    fn solve(input: &Self::Input) -> Result<Self::Solution> {
+        let mut machine = Machine::new(input);
+        match machine.run() {
+            Outcome::Loop => Ok(machine.accum),
+            outcome => bail!("machine failed to infinite loop: {:?}", outcome),
+        }
     }
 }
 
-struct InfiniteLoop;
+struct PartTwo;
+
+impl Solve<'_> for PartTwo {
+    type Input = Program;
+    type Solution = isize;
 
-#[derive(Clone, Debug)]
-struct Machine {
-    accum: isize,
-    instrs: Vec<Instr>,
-    pc: usize,
-    seen: Vec<bool>,
+    fn solve(input: &Self::Input) -> Result<Self::Solution> {
+        let (_, accum) = repair(input)?;
+        Ok(accum)
+    }
 }
 
-impl Machine {
-    fn new(instrs: &[Instr]) -> Machine {
-        Machine {
-            accum: 0,
-            instrs: instrs.to_vec(),
-            pc: 0,
-            seen: vec![false; instrs.len()],
-        }
+/// Finds the single instruction change that makes the program halt, and
+/// returns it with the value of the accumulator once the patched program
+/// has halted.
+fn repair(program: &Program) -> Result<(Patch, isize)> {
+    let patch = program
+        .find_repair()
+        .ok_or_else(|| anyhow!("no single instruction change makes the program halt"))?;
+    let patched = program.patched(&patch);
+    let mut machine = Machine::new(&patched);
+    match machine.run() {
+        Outcome::Halt => Ok((patch, machine.accum)),
+        outcome => bail!("patch {} failed to halt: {:?}", patch, outcome),
     }
+}
+
+const USAGE: &str = "usage: day08 (trace | debug | disasm | cfg [--dot] | asm PATH | repair)";
+
+const HELP: &str = "\
+commands:
+    s, step [N]         run one (or N) instructions
+    c, continue         run until a breakpoint, watch or the end
+    u, until PC         run until the program counter reaches PC
+    b, break PC         set a breakpoint at PC
+    d, delete PC        delete the breakpoint at PC
+    w, watch            toggle stopping when the accumulator changes
+    p, print            show the machine state
+    t, trace [N]        show the last 10 (or N) instructions run
+    l, loop             show the instructions of the loop, once found
+    e, edit PC INSTR    replace the instruction at PC and restart
+    r, restart          run the program again from the start
+    q, quit             exit the debugger";
+
+/// What the debugger prompt should do after a command.
+enum Action {
+    Prompt,
+    Edit(usize, Instr),
+    Restart,
+    Quit,
+}
 
-    fn step(&mut self) -> usize {
-        match self.instrs[self.pc] {
-            Instr::Acc(arg) => {
-                self.accum += arg;
-                self.pc += 1;
+fn debug_command(debugger: &mut Debugger, words: &[&str]) -> Result<Action> {
+    match *words {
+        [] | ["s"] | ["step"] => println!("{}", debugger.step()),
+        ["s", count] | ["step", count] => {
+            for _ in 0..count.parse::<usize>()? {
+                match debugger.step() {
+                    Stop::Stepped => {}
+                    stop => {
+                        println!("{}", stop);
+                        break;
+                    }
+                }
             }
-            Instr::Jmp(arg) => {
-                self.pc = (self.pc as isize + arg) as usize;
+        }
+        ["c"] | ["continue"] => println!("{}", debugger.resume()),
+        ["u", pc] | ["until", pc] => println!("{}", debugger.run_until(pc.parse()?)),
+        ["b", pc] | ["break", pc] => {
+            debugger.add_breakpoint(pc.parse()?);
+        }
+        ["d", pc] | ["delete", pc] => {
+            if !debugger.remove_breakpoint(pc.parse()?) {
+                println!("no breakpoint at {}", pc);
             }
-            Instr::Nop(_) => {
-                self.pc += 1;
+        }
+        ["w"] | ["watch"] => {
+            let watch = !debugger.is_watching_accum();
+            debugger.watch_accum(watch);
+            println!("watch {}", if watch { "on" } else { "off" });
+        }
+        ["p"] | ["print"] => {
+            let machine = debugger.machine();
+            println!(
+                "pc = {}, acc = {}, steps = {}",
+                machine.pc, machine.accum, machine.steps
+            );
+            if let Some(instr) = machine.instr() {
+                println!("next: {}", instr);
             }
+            let breakpoints = debugger.breakpoints().map(|pc| pc.to_string());
+            println!(
+                "breakpoints: {}",
+                breakpoints.collect::<Vec<_>>().join(", ")
+            );
         }
-        self.pc
+        ["t"] | ["trace"] => print_trace(debugger.trace(), 10),
+        ["t", count] | ["trace", count] => print_trace(debugger.trace(), count.parse()?),
+        ["l"] | ["loop"] => match debugger.loop_report() {
+            Some(entries) => print_trace(entries, entries.len()),
+            None => println!("no loop found yet"),
+        },
+        ["e", pc, mnemonic, arg] | ["edit", pc, mnemonic, arg] => {
+            let line = format!("{} {}", mnemonic, arg);
+            let instr = InstructionSet::default().parse_instr(&line)?;
+            return Ok(Action::Edit(pc.parse()?, instr));
+        }
+        ["r"] | ["restart"] => return Ok(Action::Restart),
+        ["q"] | ["quit"] => return Ok(Action::Quit),
+        _ => println!("{}", HELP),
     }
+    Ok(Action::Prompt)
+}
 
-    fn run(&mut self) -> Result<(), InfiniteLoop> {
-        loop {
-            let next = self.step();
-            if next == self.instrs.len() {
-                return Ok(());
-            } else if self.seen[next] {
-                return Err(InfiniteLoop);
-            }
-            self.seen[next] = true;
-        }
+fn print_trace(entries: &[TraceEntry], count: usize) {
+    for entry in &entries[entries.len().saturating_sub(count)..] {
+        println!("{}", entry);
     }
 }
 
-struct PartOne;
+/// Reads debugger commands from standard input until `quit` or end of input.
+fn debug(mut program: Program) -> Result<()> {
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    let mut breakpoints = Vec::new();
+    let mut watch = false;
+    loop {
+        let mut debugger = Debugger::new(&program);
+        for &pc in &breakpoints {
+            debugger.add_breakpoint(pc);
+        }
+        debugger.watch_accum(watch);
 
-impl Solve<'_> for PartOne {
-    type Input = Program;
-    type Solution = isize;
+        let action = loop {
+            print!("{:>5}> ", debugger.machine().pc);
+            io::stdout().flush()?;
+            let line = match lines.next() {
+                Some(line) => line?,
+                None => break Action::Quit,
+            };
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            match debug_command(&mut debugger, &words) {
+                Ok(Action::Prompt) => {}
+                Ok(action) => break action,
+                Err(err) => println!("error: {}", err),
+            }
+        };
+        breakpoints = debugger.breakpoints().collect();
+        watch = debugger.is_watching_accum();
 
-    fn solve(input: &Self::Input) -> Result<Self::Solution> {
-        let mut machine = Machine::new(&input.instrs);
-        match machine.run() {
-            Err(InfiniteLoop) => Ok(machine.accum),
-            Ok(()) => bail!("machine failed to infinite loop"),
+        match action {
+            Action::Prompt | Action::Restart => {}
+            Action::Edit(pc, instr) => match program.instrs.get_mut(pc) {
+                Some(old) => *old = instr,
+                None => println!("no instruction at {}", pc),
+            },
+            Action::Quit => return Ok(()),
         }
+        println!("restarted");
     }
 }
 
-struct PartTwo;
-
-impl Solve<'_> for PartTwo {
-    type Input = Program;
-    type Solution = isize;
-
-    fn solve(input: &Self::Input) -> Result<Self::Solution> {
-        for index in 0..input.instrs.len() {
-            let mut input = input.instrs.clone();
-            input[index] = match input[index] {
-                Instr::Acc(arg) => Instr::Acc(arg),
-                Instr::Jmp(arg) => Instr::Nop(arg),
-                Instr::Nop(arg) => Instr::Jmp(arg),
-            };
+/// Prints every instruction run until the program finishes, followed by the
+/// instructions making up the loop if it has one.
+fn trace(program: &Program) {
+    let mut debugger = Debugger::new(program);
+    let stop = debugger.resume();
+    print_trace(debugger.trace(), debugger.trace().len());
+    println!("{}", stop);
+    if let Some(entries) = debugger.loop_report() {
+        println!("loop of {} instructions:", entries.len());
+        print_trace(entries, entries.len());
+    }
+}
 
-            let mut machine = Machine::new(&input);
-            match machine.run() {
-                Ok(()) => return Ok(machine.accum),
-                Err(InfiniteLoop) => continue,
+fn cli(input_str: &str, args: &[String]) -> Result<()> {
+    let program = Program::parse(input_str)?;
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    match args[..] {
+        ["trace"] => trace(&program),
+        ["debug"] => debug(program)?,
+        ["disasm"] => print!("{}", asm::disassemble(&program)),
+        ["cfg"] => print!("{}", ControlFlowGraph::new(&program)),
+        ["cfg", "--dot"] => print!("{}", ControlFlowGraph::new(&program).to_dot()),
+        ["asm", path] => {
+            let source = fs::read_to_string(path)?;
+            let program = asm::assemble(&InstructionSet::default(), &source)?;
+            for instr in &program.instrs {
+                println!("{}", instr);
             }
         }
-        bail!("machine always infinite loops");
+        ["repair"] => {
+            let (patch, accum) = repair(&program)?;
+            println!("{}", patch);
+            println!("[day08] halts with accumulator {}", accum);
+        }
+        _ => bail!("unexpected arguments {:?}\n{}", args, USAGE),
     }
+    Ok(())
 }
 
-aoc::main!(day08);
+aoc::main!(day08, cli = cli);
 
 #[cfg(test)]
 mod examples {
@@ -138,6 +253,24 @@ mod examples {
 
         assert_eq!(PartOne::solve(&input).unwrap(), 5);
         assert_eq!(PartTwo::solve(&input).unwrap(), 8);
+        let (patch, accum) = repair(&input).unwrap();
+        assert_eq!(patch.to_string(), "7: jmp -4 -> nop -4");
+        assert_eq!(accum, 8);
+    }
+
+    #[test]
+    fn out_of_bounds_repairs() {
+        let input = Program::parse(indoc! {"
+            nop -3
+            jmp +2
+            acc +5
+            jmp -1
+            acc +7
+        "})
+        .unwrap();
+
+        assert_eq!(PartOne::solve(&input).unwrap(), 5);
+        assert_eq!(PartTwo::solve(&input).unwrap(), 7);
     }
 }
 
@@ -1,5 +1,6 @@
-use anyhow::bail;
+use anyhow::{anyhow, bail};
 use aoc::{Parse, Result, Solve};
+use std::time::Duration;
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 enum Position {
@@ -11,6 +12,13 @@ enum Position {
 #[derive(Debug, Clone, Eq, PartialEq)]
 struct SeatLayout {
     seats: Vec<Vec<Position>>,
+    /// The `(row, col)` of every non-floor position, in row-major order.
+    /// Seats are referred to by their index into this list.
+    cells: Vec<(usize, usize)>,
+    /// For each seat, the indices of the seats immediately around it.
+    moore: Vec<Vec<usize>>,
+    /// For each seat, the indices of the first seat visible in each direction.
+    visible: Vec<Vec<usize>>,
 }
 
 impl<'a> Parse<'a> for SeatLayout {
@@ -27,7 +35,7 @@ impl<'a> Parse<'a> for SeatLayout {
         }
         let lines = input_str.lines().map(str::trim);
         let seats = lines.map(parse_line).collect::<Result<_>>()?;
-        Ok(SeatLayout { seats })
+        Ok(SeatLayout::new(seats))
     }
 }
 
@@ -43,112 +51,187 @@ const DIRECTIONS: [(isize, isize); 8] = [
 ];
 
 impl SeatLayout {
+    fn new(seats: Vec<Vec<Position>>) -> Self {
+        let mut layout = SeatLayout {
+            seats,
+            cells: Vec::new(),
+            moore: Vec::new(),
+            visible: Vec::new(),
+        };
+        for (row, line) in layout.seats.iter().enumerate() {
+            for (col, &pos) in line.iter().enumerate() {
+                if pos != Position::Floor {
+                    layout.cells.push((row, col));
+                }
+            }
+        }
+        let index = |(row, col)| layout.cells.binary_search(&(row, col)).ok();
+        let moore = layout
+            .cells
+            .iter()
+            .map(|&(row, col)| layout.moore_cells(row, col).filter_map(index).collect())
+            .collect();
+        let visible = layout
+            .cells
+            .iter()
+            .map(|&(row, col)| layout.visible_cells(row, col).filter_map(index).collect())
+            .collect();
+        layout.moore = moore;
+        layout.visible = visible;
+        layout
+    }
+
     fn checked_get(&self, row: isize, col: isize) -> Option<Position> {
         use std::convert::TryFrom;
         let row = usize::try_from(row).ok()?;
         let col = usize::try_from(col).ok()?;
-        if row < self.seats.len() && col < self.seats[0].len() {
-            Some(self.seats[row][col])
-        } else {
-            None
-        }
+        self.seats.get(row)?.get(col).copied()
     }
 
-    fn occupied_moore(&self, row: usize, col: usize) -> usize {
-        DIRECTIONS
-            .iter()
-            .map(|(dr, dc)| {
-                let (row, col) = (row as isize + dr, col as isize + dc);
-                self.checked_get(row, col)
-            })
-            .filter(|&pos| pos == Some(Position::OccupiedSeat))
-            .count()
+    fn moore_cells(&self, row: usize, col: usize) -> impl Iterator<Item = (usize, usize)> + '_ {
+        DIRECTIONS.iter().filter_map(move |(dr, dc)| {
+            let (row, col) = (row as isize + dr, col as isize + dc);
+            match self.checked_get(row, col)? {
+                Position::Floor => None,
+                _ => Some((row as usize, col as usize)),
+            }
+        })
     }
 
-    fn occupied_visible(&self, row: usize, col: usize) -> usize {
-        DIRECTIONS
-            .iter()
-            .map(|(dr, dc)| {
-                for dist in 1.. {
-                    let row = row as isize + dist * dr;
-                    let col = col as isize + dist * dc;
-                    match self.checked_get(row, col) {
-                        None => return None,
-                        Some(Position::Floor) => continue,
-                        Some(seat) => return Some(seat),
-                    }
+    fn visible_cells(&self, row: usize, col: usize) -> impl Iterator<Item = (usize, usize)> + '_ {
+        DIRECTIONS.iter().filter_map(move |(dr, dc)| {
+            for dist in 1.. {
+                let row = row as isize + dist * dr;
+                let col = col as isize + dist * dc;
+                match self.checked_get(row, col)? {
+                    Position::Floor => continue,
+                    _ => return Some((row as usize, col as usize)),
                 }
-                None
-            })
-            .filter(|&pos| pos == Some(Position::OccupiedSeat))
-            .count()
+            }
+            None
+        })
     }
 }
 
+/// Steps a seat layout until it stops changing, only re-evaluating the seats
+/// whose neighbours changed during the previous generation.
 #[derive(Debug)]
-struct Simulator<O, R> {
-    read: SeatLayout,
-    write: SeatLayout,
-    occupied: O,
+struct Simulator<'a, R> {
+    layout: &'a SeatLayout,
+    neighbours: &'a [Vec<usize>],
     rule: R,
+    state: Vec<Position>,
+    active: Vec<usize>,
+    queued: Vec<bool>,
+    changed: usize,
 }
 
-impl<O, R> Simulator<O, R>
+impl<'a, R> Simulator<'a, R>
 where
-    O: Fn(&SeatLayout, usize, usize) -> usize,
     R: Fn(Position, usize) -> Position,
 {
-    fn step(&mut self) {
-        for row in 0..self.read.seats.len() {
-            for col in 0..self.read.seats[row].len() {
-                let pos = self.read.seats[row][col];
-                let occupied = (self.occupied)(&self.read, row, col);
-                self.write.seats[row][col] = (self.rule)(pos, occupied);
+    fn new(layout: &'a SeatLayout, neighbours: &'a [Vec<usize>], rule: R) -> Self {
+        let state = layout
+            .cells
+            .iter()
+            .map(|&(row, col)| layout.seats[row][col])
+            .collect();
+        Simulator {
+            layout,
+            neighbours,
+            rule,
+            state,
+            active: (0..layout.cells.len()).collect(),
+            queued: vec![true; layout.cells.len()],
+            changed: 0,
+        }
+    }
+
+    /// Advances one generation and returns the number of seats that changed.
+    fn step(&mut self) -> usize {
+        let mut updates = Vec::new();
+        for seat in std::mem::take(&mut self.active) {
+            self.queued[seat] = false;
+            let occupied = self.neighbours[seat]
+                .iter()
+                .filter(|&&other| self.state[other] == Position::OccupiedSeat)
+                .count();
+            let next = (self.rule)(self.state[seat], occupied);
+            if next != self.state[seat] {
+                updates.push((seat, next));
+            }
+        }
+
+        self.changed = updates.len();
+        for (seat, next) in updates {
+            self.state[seat] = next;
+            for &seat in std::iter::once(&seat).chain(&self.neighbours[seat]) {
+                if !self.queued[seat] {
+                    self.queued[seat] = true;
+                    self.active.push(seat);
+                }
             }
         }
-        std::mem::swap(&mut self.read, &mut self.write);
+        self.changed
     }
 
     fn run(&mut self) {
-        loop {
-            self.step();
-            if self.read == self.write {
+        while self.step() > 0 {}
+    }
+
+    /// Runs to the fixpoint like `run`, calling `on_generation` with the
+    /// initial state and again after every generation in which a seat changed.
+    fn run_with<F>(&mut self, mut on_generation: F) -> Result<()>
+    where
+        F: FnMut(usize, &Self) -> Result<()>,
+    {
+        on_generation(0, self)?;
+        for generation in 1.. {
+            if self.step() == 0 {
                 break;
             }
+            on_generation(generation, self)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the grid of positions with every seat in its current state.
+    fn seats(&self) -> Vec<Vec<Position>> {
+        let mut seats = self.layout.seats.clone();
+        for (&(row, col), &pos) in self.layout.cells.iter().zip(&self.state) {
+            seats[row][col] = pos;
         }
+        seats
     }
 
     fn occupied_count(&self) -> usize {
-        self.read
-            .seats
+        self.state
             .iter()
-            .flatten()
             .filter(|&&seat| seat == Position::OccupiedSeat)
             .count()
     }
 }
 
+type Rule = fn(Position, usize) -> Position;
+
 struct PartOne;
 
+impl PartOne {
+    fn rule(position: Position, occupied: usize) -> Position {
+        match position {
+            Position::OccupiedSeat if occupied >= 4 => Position::EmptySeat,
+            Position::EmptySeat if occupied == 0 => Position::OccupiedSeat,
+            other => other,
+        }
+    }
+}
+
 impl Solve<'_> for PartOne {
     type Input = SeatLayout;
     type Solution = usize;
 
     fn solve(input: &Self::Input) -> Result<Self::Solution> {
-        fn rule(position: Position, occupied: usize) -> Position {
-            match position {
-                Position::OccupiedSeat if occupied >= 4 => Position::EmptySeat,
-                Position::EmptySeat if occupied == 0 => Position::OccupiedSeat,
-                other => other,
-            }
-        }
-
-        let mut sim = Simulator {
-            read: input.clone(),
-            write: input.clone(),
-            occupied: SeatLayout::occupied_moore,
-            rule,
-        };
+        let mut sim = Simulator::new(input, &input.moore, PartOne::rule);
 
         sim.run();
 
@@ -158,33 +241,196 @@ impl Solve<'_> for PartOne {
 
 struct PartTwo;
 
+impl PartTwo {
+    fn rule(position: Position, occupied: usize) -> Position {
+        match position {
+            Position::OccupiedSeat if occupied >= 5 => Position::EmptySeat,
+            Position::EmptySeat if occupied == 0 => Position::OccupiedSeat,
+            other => other,
+        }
+    }
+}
+
 impl Solve<'_> for PartTwo {
     type Input = SeatLayout;
     type Solution = usize;
 
     fn solve(input: &Self::Input) -> Result<Self::Solution> {
-        fn rule(position: Position, occupied: usize) -> Position {
-            match position {
-                Position::OccupiedSeat if occupied >= 5 => Position::EmptySeat,
-                Position::EmptySeat if occupied == 0 => Position::OccupiedSeat,
-                other => other,
+        let mut sim = Simulator::new(input, &input.visible, PartTwo::rule);
+
+        sim.run();
+
+        Ok(sim.occupied_count())
+    }
+}
+
+mod render {
+    use super::Position;
+    use anyhow::{anyhow, ensure};
+    use aoc::Result;
+    use gif::{Encoder, Frame, Repeat};
+    use std::{
+        convert::TryFrom,
+        fs::File,
+        io::{self, BufWriter, Write},
+        path::Path,
+        thread,
+        time::Duration,
+    };
+
+    pub trait Renderer {
+        fn frame(&mut self, generation: usize, seats: &[Vec<Position>]) -> Result<()>;
+    }
+
+    /// Redraws the layout in place on an ANSI terminal, pausing between frames.
+    pub struct Terminal {
+        pub delay: Duration,
+    }
+
+    impl Renderer for Terminal {
+        fn frame(&mut self, generation: usize, seats: &[Vec<Position>]) -> Result<()> {
+            let stdout = io::stdout();
+            let mut out = stdout.lock();
+            write!(out, "\x1b[2J\x1b[H")?;
+            writeln!(out, "generation {}", generation)?;
+            for line in seats {
+                for pos in line {
+                    match pos {
+                        Position::Floor => write!(out, "\x1b[90m.")?,
+                        Position::EmptySeat => write!(out, "\x1b[32mL")?,
+                        Position::OccupiedSeat => write!(out, "\x1b[31m#")?,
+                    }
+                }
+                writeln!(out, "\x1b[0m")?;
             }
+            out.flush()?;
+            thread::sleep(self.delay);
+            Ok(())
         }
+    }
 
-        let mut sim = Simulator {
-            read: input.clone(),
-            write: input.clone(),
-            occupied: SeatLayout::occupied_visible,
-            rule,
-        };
+    /// Palette indices line up with the order of the `Position` variants.
+    const PALETTE: [u8; 9] = [
+        0x30, 0x30, 0x30, // floor
+        0x40, 0xa0, 0x40, // empty seat
+        0xd0, 0x40, 0x40, // occupied seat
+    ];
 
-        sim.run();
+    /// Writes every generation as a frame of an animated GIF.
+    pub struct Gif {
+        encoder: Encoder<BufWriter<File>>,
+        scale: usize,
+        width: u16,
+        height: u16,
+        delay: u16,
+    }
 
-        Ok(sim.occupied_count())
+    impl Gif {
+        /// Starts writing a GIF of a layout shaped like `seats`, which must be
+        /// a non-empty rectangle, drawing each position as a square `scale`
+        /// pixels across.
+        pub fn create(
+            path: &Path,
+            seats: &[Vec<Position>],
+            scale: usize,
+            delay: Duration,
+        ) -> Result<Self> {
+            ensure!(scale > 0, "scale must be at least 1 pixel");
+            let rows = seats.len();
+            let cols = seats.first().map_or(0, Vec::len);
+            ensure!(rows > 0 && cols > 0, "cannot draw an empty layout");
+            ensure!(
+                seats.iter().all(|line| line.len() == cols),
+                "cannot draw a layout with rows of different lengths"
+            );
+            let pixels = |count: usize| {
+                let pixels = count.checked_mul(scale);
+                let pixels = pixels.and_then(|pixels| u16::try_from(pixels).ok());
+                pixels.ok_or_else(|| anyhow!("{}x{} layout is too large to draw", cols, rows))
+            };
+            let (width, height) = (pixels(cols)?, pixels(rows)?);
+            let file = BufWriter::new(File::create(path)?);
+            let mut encoder = Encoder::new(file, width, height, &PALETTE)?;
+            encoder.set_repeat(Repeat::Infinite)?;
+            let delay = u16::try_from(delay.as_millis() / 10)?;
+            Ok(Gif {
+                encoder,
+                scale,
+                width,
+                height,
+                delay,
+            })
+        }
+    }
+
+    impl Renderer for Gif {
+        fn frame(&mut self, _generation: usize, seats: &[Vec<Position>]) -> Result<()> {
+            let mut pixels = Vec::new();
+            for line in seats {
+                let mut row = Vec::new();
+                for &pos in line {
+                    row.resize(row.len() + self.scale, pos as u8);
+                }
+                for _ in 0..self.scale {
+                    pixels.extend_from_slice(&row);
+                }
+            }
+            ensure!(
+                pixels.len() == usize::from(self.width) * usize::from(self.height),
+                "layout changed shape since the GIF was created"
+            );
+            let mut frame = Frame::from_indexed_pixels(self.width, self.height, &pixels, None);
+            frame.delay = self.delay;
+            self.encoder.write_frame(&frame)?;
+            Ok(())
+        }
+    }
+}
+
+const USAGE: &str = "usage: day11 [--part 1|2] [--delay MS] [--terminal | --gif PATH [--scale PX]]";
+
+/// Animates the simulation for one part of the puzzle, either on the terminal
+/// or into an animated GIF.
+fn cli(input_str: &str, args: &[String]) -> Result<()> {
+    use render::{Gif, Renderer, Terminal};
+
+    let mut part = 1;
+    let mut delay = Duration::from_millis(100);
+    let mut scale = 4;
+    let mut gif = None;
+    let mut terminal = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow!("{} needs a value", arg));
+        match arg.as_str() {
+            "--part" => part = value()?.parse()?,
+            "--delay" => delay = Duration::from_millis(value()?.parse()?),
+            "--scale" => scale = value()?.parse()?,
+            "--gif" => gif = Some(value()?.clone()),
+            "--terminal" => terminal = true,
+            _ => bail!("unexpected argument {:?}\n{}", arg, USAGE),
+        }
     }
+
+    let layout = SeatLayout::parse(input_str)?;
+    let (neighbours, rule): (_, Rule) = match part {
+        1 => (&layout.moore, PartOne::rule),
+        2 => (&layout.visible, PartTwo::rule),
+        _ => bail!("no such part {}\n{}", part, USAGE),
+    };
+    let mut renderer: Box<dyn Renderer> = match (gif, terminal) {
+        (Some(path), false) => Box::new(Gif::create(path.as_ref(), &layout.seats, scale, delay)?),
+        (None, true) => Box::new(Terminal { delay }),
+        _ => bail!("exactly one of --terminal or --gif is required\n{}", USAGE),
+    };
+
+    let mut sim = Simulator::new(&layout, neighbours, rule);
+    sim.run_with(|generation, sim| renderer.frame(generation, &sim.seats()))?;
+    println!("[day11] Occupied seats: {}", sim.occupied_count());
+    Ok(())
 }
 
-aoc::main!(day11);
+aoc::main!(day11, cli = cli);
 
 #[cfg(test)]
 mod examples {
@@ -210,6 +456,25 @@ mod examples {
         assert_eq!(PartOne::solve(&input).unwrap(), 37);
         assert_eq!(PartTwo::solve(&input).unwrap(), 26);
     }
+
+    #[test]
+    fn gif_shapes() {
+        fn error(seats: &[Vec<Position>], scale: usize) -> String {
+            let path = std::path::Path::new("/nonexistent/seats.gif");
+            let gif = render::Gif::create(path, seats, scale, Duration::from_millis(100));
+            gif.err().unwrap().to_string()
+        }
+
+        let seats = SeatLayout::parse("L.\n#L").unwrap().seats;
+        assert_eq!(error(&seats, 0), "scale must be at least 1 pixel");
+        assert_eq!(error(&[], 4), "cannot draw an empty layout");
+        let ragged = SeatLayout::parse("L.\n#").unwrap().seats;
+        assert_eq!(
+            error(&ragged, 4),
+            "cannot draw a layout with rows of different lengths"
+        );
+        assert_eq!(error(&seats, 40000), "2x2 layout is too large to draw");
+    }
 }
 
 aoc::solved!(day11, PartOne = 2324, PartTwo = 2068);
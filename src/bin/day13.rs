@@ -1,5 +1,7 @@
-use anyhow::anyhow;
+use anyhow::{anyhow, bail, ensure};
+use aoc::number_theory::{crt, explain_crt, lcm, Congruence};
 use aoc::{Parse, Result, Solve};
+use std::{cmp::Reverse, collections::BinaryHeap, convert::TryFrom};
 
 #[derive(Debug, Copy, Clone)]
 struct Bus {
@@ -27,6 +29,7 @@ impl<'a> Parse<'a> for BusSchedule {
             .filter(|&(_, bus)| bus != "x")
             .map(|(index, bus)| {
                 let headway = bus.parse()?;
+                ensure!(headway > 0, "bus {} has no headway", index);
                 let index = index as u64;
                 Ok(Bus { headway, index })
             })
@@ -35,6 +38,83 @@ impl<'a> Parse<'a> for BusSchedule {
     }
 }
 
+impl Bus {
+    /// The times `t` at which this bus departs `index` minutes after `t`.
+    fn congruence(&self) -> Result<Congruence> {
+        Congruence::new(-i128::from(self.index), i128::from(self.headway))
+    }
+}
+
+impl BusSchedule {
+    /// Returns the first `count` departures strictly after `time`, as pairs
+    /// of departure time and headway, earliest first. Fails if any of them
+    /// would be too late to represent.
+    fn departures(&self, time: u64, count: usize) -> Result<Vec<(u64, u64)>> {
+        let mut queue = BinaryHeap::new();
+        let mut overflowed = false;
+        for bus in &self.buses {
+            let next = (time / bus.headway)
+                .checked_add(1)
+                .and_then(|periods| periods.checked_mul(bus.headway));
+            match next {
+                Some(next) => queue.push(Reverse((next, bus.headway))),
+                None => overflowed = true,
+            }
+        }
+        let mut departures = Vec::new();
+        while departures.len() < count {
+            let Reverse((time, headway)) = match queue.pop() {
+                Some(next) => next,
+                None if overflowed => bail!(
+                    "departure {} is later than the last representable time, {}",
+                    departures.len() + 1,
+                    u64::MAX
+                ),
+                None => break,
+            };
+            departures.push((time, headway));
+            match time.checked_add(headway) {
+                Some(next) => queue.push(Reverse((next, headway))),
+                None => overflowed = true,
+            }
+        }
+        Ok(departures)
+    }
+
+    /// Returns the first time `t` strictly after `time` at which each of the
+    /// given buses departs `index` minutes after `t`, if there ever is one.
+    /// The buses must all run on this schedule.
+    fn align(&self, buses: &[Bus], time: u64) -> Result<Option<u64>> {
+        for bus in buses {
+            ensure!(
+                self.buses.iter().any(|other| other.headway == bus.headway),
+                "no bus has headway {}",
+                bus.headway
+            );
+        }
+        let congruences = buses
+            .iter()
+            .map(Bus::congruence)
+            .collect::<Result<Vec<_>>>()?;
+        let solution = match crt(&congruences)? {
+            Some(solution) => solution,
+            None => return Ok(None),
+        };
+        let time = i128::from(time);
+        let periods = (time - solution.residue).div_euclid(solution.modulus) + 1;
+        let aligned = solution.residue + periods * solution.modulus;
+        Ok(Some(u64::try_from(aligned)?))
+    }
+
+    /// Returns the number of minutes after which every bus's timetable
+    /// repeats at once.
+    fn period(&self) -> Result<i128> {
+        self.buses.iter().try_fold(1, |period, bus| {
+            lcm(period, i128::from(bus.headway)).ok_or_else(|| anyhow!("period overflows"))
+        })
+    }
+}
+
 struct PartOne;
 
 impl Solve<'_> for PartOne {
@@ -59,19 +139,73 @@ impl Solve<'_> for PartTwo {
     type Solution = u64;
 
     fn solve(input: &Self::Input) -> Result<Self::Solution> {
-        let mut time = 1;
-        let mut wait = 1;
-        for bus in input.buses.iter() {
-            while (time + bus.index) % bus.headway != 0 {
-                time += wait;
+        let congruences = input
+            .buses
+            .iter()
+            .map(Bus::congruence)
+            .collect::<Result<Vec<_>>>()?;
+        match crt(&congruences)? {
+            Some(solution) => Ok(u64::try_from(solution.residue)?),
+            None => bail!("the buses never line up"),
+        }
+    }
+}
+
+const USAGE: &str = "usage: day13 departures TIME COUNT
+       day13 align TIME HEADWAY:OFFSET...
+       day13 period
+       day13 explain";
+
+/// Parses a bus given as `headway:offset`.
+fn parse_offset(arg: &str) -> Result<Bus> {
+    let mut split = arg.splitn(2, ':');
+    match (split.next(), split.next()) {
+        (Some(headway), Some(index)) => Ok(Bus {
+            headway: headway.parse()?,
+            index: index.parse()?,
+        }),
+        _ => bail!("expected HEADWAY:OFFSET, found {:?}", arg),
+    }
+}
+
+fn cli(input_str: &str, args: &[String]) -> Result<()> {
+    let schedule = BusSchedule::parse(input_str)?;
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    match args[..] {
+        ["departures", time, count] => {
+            for (time, headway) in schedule.departures(time.parse()?, count.parse()?)? {
+                println!("{:>16}  bus {}", time, headway);
+            }
+        }
+        ["align", time, ref buses @ ..] if !buses.is_empty() => {
+            let buses = buses
+                .iter()
+                .map(|arg| parse_offset(arg))
+                .collect::<Result<Vec<_>>>()?;
+            match schedule.align(&buses, time.parse()?)? {
+                Some(aligned) => println!("[day13] buses align at {}", aligned),
+                None => println!("[day13] buses never align"),
             }
-            wait *= bus.headway;
         }
-        Ok(time)
+        ["explain"] => {
+            let congruences = schedule
+                .buses
+                .iter()
+                .map(Bus::congruence)
+                .collect::<Result<Vec<_>>>()?;
+            let labels = schedule
+                .buses
+                .iter()
+                .map(|bus| format!("bus {}, index {}", bus.headway, bus.index));
+            print!("{}", explain_crt(&congruences)?.labelled(labels));
+        }
+        ["period"] => println!("[day13] timetable repeats every {}", schedule.period()?),
+        _ => bail!("unexpected arguments {:?}\n{}", args, USAGE),
     }
+    Ok(())
 }
 
-aoc::main!(day13);
+aoc::main!(day13, cli = cli);
 
 #[cfg(test)]
 mod examples {
@@ -108,6 +242,71 @@ mod examples {
 
         let input = BusSchedule::parse("0\n1789,37,47,1889").unwrap();
         assert_eq!(PartTwo::solve(&input).unwrap(), 1202161486);
+
+        let input = BusSchedule::parse("0\n4,x,6").unwrap();
+        assert_eq!(PartTwo::solve(&input).unwrap(), 4);
+
+        let input = BusSchedule::parse("0\n4,6").unwrap();
+        assert!(PartTwo::solve(&input).is_err());
+    }
+
+    #[test]
+    fn queries() {
+        let input = BusSchedule::parse("939\n7,13,x,x,59,x,31,19").unwrap();
+        assert_eq!(
+            input.departures(939, 4).unwrap(),
+            vec![(944, 59), (945, 7), (949, 13), (950, 19)]
+        );
+        assert_eq!(input.period().unwrap(), 7 * 13 * 59 * 31 * 19);
+
+        let late = u64::MAX - 20;
+        let departures = input.departures(late, 2).unwrap();
+        assert_eq!(departures, vec![(u64::MAX - 16, 19), (u64::MAX - 15, 7)]);
+        assert!(input.departures(late, 20).is_err());
+        assert!(input.departures(u64::MAX, 2).is_err());
+
+        let every_minute = BusSchedule::parse("0\n1").unwrap();
+        assert!(every_minute.departures(u64::MAX, 1).is_err());
+        assert_eq!(
+            every_minute.departures(u64::MAX - 1, 1).unwrap(),
+            vec![(u64::MAX, 1)]
+        );
+
+        let buses = [
+            Bus {
+                headway: 7,
+                index: 0,
+            },
+            Bus {
+                headway: 13,
+                index: 1,
+            },
+        ];
+        assert_eq!(input.align(&buses, 0).unwrap(), Some(77));
+        assert_eq!(input.align(&buses, 77).unwrap(), Some(77 + 91));
+        assert!(input
+            .align(
+                &[Bus {
+                    headway: 5,
+                    index: 0
+                }],
+                0
+            )
+            .is_err());
+
+        let input = BusSchedule::parse("0\n4,6").unwrap();
+        let buses = [
+            Bus {
+                headway: 4,
+                index: 0,
+            },
+            Bus {
+                headway: 6,
+                index: 1,
+            },
+        ];
+        assert_eq!(input.align(&buses, 0).unwrap(), None);
+        assert_eq!(input.period().unwrap(), 12);
     }
 }
 
@@ -1,60 +1,333 @@
-use aoc::{Parse, Result, Solve};
-use reformation::Reformation;
-use std::collections::HashMap;
+use anyhow::{bail, ensure};
+use aoc::{Context, Parse, Result, Solve};
+use std::{collections::HashMap, fmt, fs};
 
-#[derive(Debug, Clone, Reformation)]
-enum Instr<'a> {
-    #[reformation("mem[{}] = {}", no_regex = true)]
+/// The number of bits in an address or value, unless a program's masks say
+/// otherwise.
+const DEFAULT_WIDTH: u32 = 36;
+
+/// The widest word the machine supports.
+const MAX_WIDTH: u32 = u64::BITS;
+
+/// Returns whether `word` fits in `width` bits.
+fn fits(word: u64, width: u32) -> bool {
+    width >= MAX_WIDTH || word >> width == 0
+}
+
+/// A bitmask, with each bit forced to 1, forced to 0, or left floating. The
+/// mask is as wide as the words it applies to.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+struct Mask {
+    width: u32,
+    ones: u64,
+    zeros: u64,
+    floating: u64,
+}
+
+impl<'a> Parse<'a> for Mask {
+    fn parse<'b: 'a>(input_str: &'b str) -> Result<Self> {
+        ensure!(
+            (1..=MAX_WIDTH as usize).contains(&input_str.len()),
+            "mask {:?} is not between 1 and {} bits long",
+            input_str,
+            MAX_WIDTH
+        );
+        let mut mask = Mask {
+            width: input_str.len() as u32,
+            ..Mask::default()
+        };
+        for ch in input_str.chars() {
+            mask.ones <<= 1;
+            mask.zeros <<= 1;
+            mask.floating <<= 1;
+            match ch {
+                '1' => mask.ones |= 1,
+                '0' => mask.zeros |= 1,
+                'X' => mask.floating |= 1,
+                _ => bail!("invalid character {:?} in mask {:?}", ch, input_str),
+            }
+        }
+        Ok(mask)
+    }
+}
+
+impl fmt::Display for Mask {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for bit in (0..self.width).rev() {
+            let ch = if self.ones >> bit & 1 == 1 {
+                '1'
+            } else if self.floating >> bit & 1 == 1 {
+                'X'
+            } else {
+                '0'
+            };
+            write!(f, "{}", ch)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Instr {
     Write(u64, u64),
-    #[reformation("mask = {}")]
-    SetMask(&'a str),
+    SetMask(Mask),
+}
+
+impl<'a> Parse<'a> for Instr {
+    fn parse<'b: 'a>(input_str: &'b str) -> Result<Self> {
+        let mut split = input_str.splitn(2, " = ");
+        let (target, value) = match (split.next(), split.next()) {
+            (Some(target), Some(value)) => (target, value),
+            _ => bail!("could not split instruction: {:?}", input_str),
+        };
+        if target == "mask" {
+            return Ok(Instr::SetMask(Mask::parse(value)?));
+        }
+        let addr = match target
+            .strip_prefix("mem[")
+            .and_then(|t| t.strip_suffix(']'))
+        {
+            Some(addr) => addr.parse()?,
+            None => bail!("unknown instruction target {:?}", target),
+        };
+        Ok(Instr::Write(addr, value.parse()?))
+    }
+}
+
+impl fmt::Display for Instr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Instr::Write(addr, val) => write!(f, "mem[{}] = {}", addr, val),
+            Instr::SetMask(mask) => write!(f, "mask = {}", mask),
+        }
+    }
 }
 
 #[derive(Debug)]
-struct Program<'a> {
-    instrs: Vec<Instr<'a>>,
+struct Program {
+    instrs: Vec<Instr>,
+    /// The width shared by all the program's masks, if it has any.
+    width: Option<u32>,
 }
 
-impl<'a> Parse<'a> for Program<'a> {
+impl<'a> Parse<'a> for Program {
     fn parse<'b: 'a>(input_str: &'b str) -> Result<Self> {
-        let lines = input_str.lines().map(str::trim);
-        let instrs = lines.map(Instr::parse).collect::<Result<_, _>>()?;
-        Ok(Program { instrs })
+        let mut instrs = Vec::new();
+        let mut width = None;
+        for (number, line) in input_str.lines().map(str::trim).enumerate() {
+            let instr = Instr::parse(line).with_context(|| format!("line {}", number + 1))?;
+            if let Instr::SetMask(mask) = instr {
+                let width = *width.get_or_insert(mask.width);
+                ensure!(
+                    mask.width == width,
+                    "line {}: mask is {} bits wide, but earlier masks are {}",
+                    number + 1,
+                    mask.width,
+                    width
+                );
+            }
+            instrs.push(instr);
+        }
+        Ok(Program { instrs, width })
+    }
+}
+
+/// A set of addresses, written as an address whose `floating` bits may each
+/// be either 0 or 1. The floating bits of `fixed` are always 0.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct Pattern {
+    fixed: u64,
+    floating: u64,
+}
+
+impl Pattern {
+    fn single(addr: u64) -> Self {
+        Pattern {
+            fixed: addr,
+            floating: 0,
+        }
+    }
+
+    fn len(&self) -> u128 {
+        1 << self.floating.count_ones()
+    }
+
+    fn intersects(&self, other: &Pattern) -> bool {
+        let both_fixed = !self.floating & !other.floating;
+        (self.fixed ^ other.fixed) & both_fixed == 0
+    }
+
+    /// Returns disjoint patterns covering the addresses in `self` but not in
+    /// `other`, fixing one more of `self`'s floating bits in each.
+    fn difference(&self, other: &Pattern) -> Vec<Pattern> {
+        if !self.intersects(other) {
+            return vec![*self];
+        }
+        let mut pieces = Vec::new();
+        let mut rest = *self;
+        let mut split = self.floating & !other.floating;
+        while split != 0 {
+            let bit = split & split.wrapping_neg();
+            split &= !bit;
+            rest.floating &= !bit;
+            pieces.push(Pattern {
+                fixed: rest.fixed | (!other.fixed & bit),
+                floating: rest.floating,
+            });
+            rest.fixed |= other.fixed & bit;
+        }
+        pieces
+    }
+
+    /// Formats the pattern as a `width`-bit word in `radix`, or in binary with
+    /// an `X` for each floating bit if it matches more than one address.
+    fn format(&self, width: u32, radix: Radix) -> String {
+        if self.floating == 0 {
+            return radix.format(self.fixed, width);
+        }
+        (0..width)
+            .rev()
+            .map(
+                |bit| match (self.floating >> bit & 1, self.fixed >> bit & 1) {
+                    (1, _) => 'X',
+                    (_, 1) => '1',
+                    _ => '0',
+                },
+            )
+            .collect()
+    }
+}
+
+/// How to write out addresses and values.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Radix {
+    Decimal,
+    Hex,
+    Binary,
+}
+
+impl Radix {
+    /// Formats `word`, padding hex and binary to the digits `width` bits need.
+    fn format(self, word: u64, width: u32) -> String {
+        match self {
+            Radix::Decimal => word.to_string(),
+            Radix::Hex => format!("{:0w$x}", word, w = (width as usize).div_ceil(4)),
+            Radix::Binary => format!("{:0w$b}", word, w = width as usize),
+        }
     }
 }
 
 trait Memory {
-    fn set_mask(&mut self, mask: &str) -> Result<()>;
-    fn write(&mut self, addr: u64, val: u64) -> Result<()>;
-    fn sum_contents(&self) -> u64;
+    fn set_mask(&mut self, mask: Mask);
+
+    /// Writes `val` to `addr` through the current mask, returning the
+    /// addresses written to and the value stored at each of them.
+    fn write(&mut self, addr: u64, val: u64) -> Result<(Pattern, u64)>;
+
+    /// Returns disjoint address patterns with the value stored at every
+    /// address they match, in order of their lowest address.
+    fn contents(&self) -> Vec<(Pattern, u64)>;
+
+    fn sum_contents(&self) -> u128 {
+        sum_contents(&self.contents())
+    }
+}
+
+/// Sums the values stored at every address of some memory's contents.
+fn sum_contents(contents: &[(Pattern, u64)]) -> u128 {
+    let contents = contents.iter();
+    contents
+        .map(|&(addrs, val)| addrs.len() * u128::from(val))
+        .sum()
+}
+
+/// An instruction the machine has just executed.
+#[derive(Debug, Copy, Clone)]
+struct Step {
+    index: usize,
+    instr: Instr,
+    /// The mask in effect after the instruction.
+    mask: Option<Mask>,
+    /// Where a write instruction stored its value, and what the value was.
+    written: Option<(Pattern, u64)>,
 }
 
 #[derive(Debug)]
 struct Machine<'a, M> {
-    program: &'a Program<'a>,
-    mask: &'a str,
+    program: &'a Program,
+    width: u32,
+    mask: Option<Mask>,
     mem: M,
 }
 
 impl<'a, M: Memory> Machine<'a, M> {
+    /// Creates a machine whose words are as wide as the program's masks.
     fn new(program: &'a Program, mem: M) -> Self {
+        let width = program.width.unwrap_or(DEFAULT_WIDTH);
         Self {
             program,
-            mask: "",
+            width,
+            mask: None,
             mem,
         }
     }
 
+    /// Creates a machine with words of the given width, which must match the
+    /// width of the program's masks.
+    fn with_width(program: &'a Program, width: u32, mem: M) -> Result<Self> {
+        ensure!(
+            (1..=MAX_WIDTH).contains(&width),
+            "word width {} is not between 1 and {}",
+            width,
+            MAX_WIDTH
+        );
+        if let Some(masks) = program.width {
+            ensure!(
+                masks == width,
+                "program has {}-bit masks, not {}-bit",
+                masks,
+                width
+            );
+        }
+        Ok(Self {
+            program,
+            width,
+            mask: None,
+            mem,
+        })
+    }
+
     fn run(&mut self) -> Result<()> {
-        for instr in &self.program.instrs {
-            match *instr {
+        self.run_with(|_| {})
+    }
+
+    /// Runs the program, passing each instruction to `trace` once it is done.
+    fn run_with(&mut self, mut trace: impl FnMut(&Step)) -> Result<()> {
+        for (index, &instr) in self.program.instrs.iter().enumerate() {
+            let mut written = None;
+            match instr {
                 Instr::Write(addr, val) => {
-                    self.mem.write(addr, val)?;
+                    ensure!(
+                        fits(addr, self.width) && fits(val, self.width),
+                        "instruction {}: mem[{}] = {} does not fit in {} bits",
+                        index + 1,
+                        addr,
+                        val,
+                        self.width
+                    );
+                    written = Some(self.mem.write(addr, val)?);
                 }
                 Instr::SetMask(mask) => {
-                    self.mem.set_mask(mask)?;
+                    self.mask = Some(mask);
+                    self.mem.set_mask(mask);
                 }
             }
+            trace(&Step {
+                index,
+                instr,
+                mask: self.mask,
+                written,
+            });
         }
         Ok(())
     }
@@ -62,35 +335,41 @@ impl<'a, M: Memory> Machine<'a, M> {
 
 #[derive(Debug, Default)]
 struct MemV1 {
-    mask_zeros: u64,
-    mask_ones: u64,
+    mask: Mask,
     contents: HashMap<u64, u64>,
 }
 
 impl Memory for MemV1 {
-    fn set_mask(&mut self, mask: &str) -> Result<()> {
-        self.mask_zeros = u64::from_str_radix(&mask.replace("X", "1"), 2)?;
-        self.mask_ones = u64::from_str_radix(&mask.replace("X", "0"), 2)?;
-        Ok(())
+    fn set_mask(&mut self, mask: Mask) {
+        self.mask = mask;
     }
 
-    fn write(&mut self, addr: u64, mut val: u64) -> Result<()> {
-        val &= self.mask_zeros;
-        val |= self.mask_ones;
+    fn write(&mut self, addr: u64, mut val: u64) -> Result<(Pattern, u64)> {
+        val &= !self.mask.zeros;
+        val |= self.mask.ones;
         self.contents.insert(addr, val);
-        Ok(())
+        Ok((Pattern::single(addr), val))
     }
 
-    fn sum_contents(&self) -> u64 {
-        self.contents.values().sum()
+    fn contents(&self) -> Vec<(Pattern, u64)> {
+        sorted_contents(&self.contents)
     }
 }
 
+fn sorted_contents(contents: &HashMap<u64, u64>) -> Vec<(Pattern, u64)> {
+    let mut contents = contents
+        .iter()
+        .map(|(&addr, &val)| (Pattern::single(addr), val))
+        .collect::<Vec<_>>();
+    contents.sort_unstable_by_key(|(addr, _)| addr.fixed);
+    contents
+}
+
 struct PartOne;
 
-impl<'a> Solve<'a> for PartOne {
-    type Input = Program<'a>;
-    type Solution = u64;
+impl Solve<'_> for PartOne {
+    type Input = Program;
+    type Solution = u128;
 
     fn solve(input: &Self::Input) -> Result<Self::Solution> {
         let mut mach = Machine::new(input, MemV1::default());
@@ -99,61 +378,193 @@ impl<'a> Solve<'a> for PartOne {
     }
 }
 
+/// Version 2 memory that expands every floating bit into concrete addresses,
+/// which is only practical for masks with few floating bits.
 #[derive(Debug, Default)]
 struct MemV2 {
-    mask: String,
+    mask: Mask,
     contents: HashMap<u64, u64>,
 }
 
 impl Memory for MemV2 {
-    fn set_mask(&mut self, mask: &str) -> Result<()> {
-        self.mask = mask.to_string();
-        Ok(())
+    fn set_mask(&mut self, mask: Mask) {
+        self.mask = mask;
     }
 
-    fn write(&mut self, addr: u64, val: u64) -> Result<()> {
-        fn write_rec(
-            mem: &mut HashMap<u64, u64>,
-            mask: &str,
-            addr: u64,
-            val: u64,
-            bit: usize,
-        ) -> Result<()> {
-            match mask.chars().nth(bit) {
-                Some('0') => write_rec(mem, mask, addr, val, bit + 1),
-                Some('1') => write_rec(mem, mask, addr | (1 << (35 - bit)), val, bit + 1),
-                Some('X') => {
-                    write_rec(mem, mask, addr & !(1 << (35 - bit)), val, bit + 1)?;
-                    write_rec(mem, mask, addr | (1 << (35 - bit)), val, bit + 1)
-                }
-                _ => {
-                    mem.insert(addr, val);
-                    Ok(())
-                }
+    fn write(&mut self, addr: u64, val: u64) -> Result<(Pattern, u64)> {
+        fn write_rec(mem: &mut HashMap<u64, u64>, floating: u64, addr: u64, val: u64) {
+            if floating == 0 {
+                mem.insert(addr, val);
+                return;
             }
+            let bit = floating & floating.wrapping_neg();
+            write_rec(mem, floating & !bit, addr & !bit, val);
+            write_rec(mem, floating & !bit, addr | bit, val);
         }
-        write_rec(&mut self.contents, &self.mask, addr, val, 0)
+        let addr = addr | self.mask.ones;
+        write_rec(&mut self.contents, self.mask.floating, addr, val);
+        let addrs = Pattern {
+            fixed: addr & !self.mask.floating,
+            floating: self.mask.floating,
+        };
+        Ok((addrs, val))
     }
 
-    fn sum_contents(&self) -> u64 {
-        self.contents.values().sum()
+    fn contents(&self) -> Vec<(Pattern, u64)> {
+        sorted_contents(&self.contents)
+    }
+}
+
+/// Version 2 memory that records each write as a single address pattern
+/// instead of expanding it, so that masks with many floating bits stay cheap.
+#[derive(Debug, Default)]
+struct SymbolicMemV2 {
+    mask: Mask,
+    writes: Vec<(Pattern, u64)>,
+}
+
+impl Memory for SymbolicMemV2 {
+    fn set_mask(&mut self, mask: Mask) {
+        self.mask = mask;
+    }
+
+    fn write(&mut self, addr: u64, val: u64) -> Result<(Pattern, u64)> {
+        let pattern = Pattern {
+            fixed: (addr | self.mask.ones) & !self.mask.floating,
+            floating: self.mask.floating,
+        };
+        self.writes.push((pattern, val));
+        Ok((pattern, val))
+    }
+
+    /// Works back from the latest write, keeping only the addresses of each
+    /// pattern that no later write has claimed.
+    fn contents(&self) -> Vec<(Pattern, u64)> {
+        let mut contents: Vec<(Pattern, u64)> = Vec::new();
+        for &(pattern, val) in self.writes.iter().rev() {
+            let mut pieces = vec![pattern];
+            for (other, _) in &contents {
+                pieces = pieces
+                    .iter()
+                    .flat_map(|piece| piece.difference(other))
+                    .collect();
+            }
+            contents.extend(pieces.into_iter().map(|piece| (piece, val)));
+        }
+        contents.sort_unstable_by_key(|(addrs, _)| addrs.fixed);
+        contents
     }
 }
 
 struct PartTwo;
 
-impl<'a> Solve<'a> for PartTwo {
-    type Input = Program<'a>;
-    type Solution = u64;
+impl Solve<'_> for PartTwo {
+    type Input = Program;
+    type Solution = u128;
 
     fn solve(input: &Self::Input) -> Result<Self::Solution> {
-        let mut mach = Machine::new(input, MemV2::default());
+        let mut mach = Machine::new(input, SymbolicMemV2::default());
         mach.run()?;
         Ok(mach.mem.sum_contents())
     }
 }
 
-aoc::main!(day14);
+const USAGE: &str = "usage: day14 (v1 | v2 [--expanded]) [--width BITS] [--input PATH]
+                   [--trace] [--dump (dec | hex | bin)]";
+
+#[derive(Debug, Default)]
+struct Options {
+    width: Option<u32>,
+    trace: bool,
+    dump: Option<Radix>,
+}
+
+/// Describes an executed instruction: the mask in effect and, for a write,
+/// the value stored and the address or pattern of addresses it went to.
+fn trace_line(step: &Step, width: u32, radix: Radix) -> String {
+    let mut line = format!("{:>5}  {}", step.index + 1, step.instr);
+    if let Some((addrs, val)) = step.written {
+        let mask = step
+            .mask
+            .map_or("none".to_string(), |mask| mask.to_string());
+        line.push_str(&format!(
+            "  (mask {}) wrote {} to {}",
+            mask,
+            radix.format(val, width),
+            addrs.format(width, radix)
+        ));
+    }
+    line
+}
+
+/// Runs a program, printing whatever `options` ask for along the way, and
+/// returns the sum of the memory's contents.
+fn execute<M: Memory>(program: &Program, mem: M, options: &Options) -> Result<u128> {
+    let mut mach = match options.width {
+        Some(width) => Machine::with_width(program, width, mem)?,
+        None => Machine::new(program, mem),
+    };
+    let width = mach.width;
+    if options.trace {
+        let radix = options.dump.unwrap_or(Radix::Decimal);
+        mach.run_with(|step| println!("{}", trace_line(step, width, radix)))?;
+    } else {
+        mach.run()?;
+    }
+    let contents = mach.mem.contents();
+    if let Some(radix) = options.dump {
+        for &(addrs, val) in &contents {
+            println!(
+                "{}  {}",
+                addrs.format(width, radix),
+                radix.format(val, width)
+            );
+        }
+    }
+    Ok(sum_contents(&contents))
+}
+
+/// Runs a program with either version of the decoder chip, optionally with
+/// an explicit word width or on a different input, e.g. `day14 v2 --width 48`.
+fn cli(input_str: &str, args: &[String]) -> Result<()> {
+    let mut args = args.iter().map(String::as_str);
+    let version = args.next();
+    let mut expanded = false;
+    let mut input = None;
+    let mut options = Options::default();
+    while let Some(arg) = args.next() {
+        match arg {
+            "--expanded" => expanded = true,
+            "--trace" => options.trace = true,
+            "--width" | "--input" | "--dump" => {
+                let value = match args.next() {
+                    Some(value) => value,
+                    None => bail!("missing value for {}\n{}", arg, USAGE),
+                };
+                match (arg, value) {
+                    ("--width", bits) => options.width = Some(bits.parse()?),
+                    ("--input", path) => input = Some(fs::read_to_string(path)?),
+                    (_, "dec") => options.dump = Some(Radix::Decimal),
+                    (_, "hex") => options.dump = Some(Radix::Hex),
+                    (_, "bin") => options.dump = Some(Radix::Binary),
+                    (_, radix) => bail!("unknown radix {:?}\n{}", radix, USAGE),
+                }
+            }
+            _ => bail!("unexpected argument {:?}\n{}", arg, USAGE),
+        }
+    }
+    let program = Program::parse(input.as_deref().unwrap_or(input_str))?;
+    let sum = match (version, expanded) {
+        (Some("v1"), false) => execute(&program, MemV1::default(), &options)?,
+        (Some("v2"), false) => execute(&program, SymbolicMemV2::default(), &options)?,
+        (Some("v2"), true) => execute(&program, MemV2::default(), &options)?,
+        _ => bail!("{}", USAGE),
+    };
+    println!("[day14] memory sums to {}", sum);
+    Ok(())
+}
+
+aoc::main!(day14, cli = cli);
 
 #[cfg(test)]
 mod examples {
@@ -184,6 +595,135 @@ mod examples {
         .unwrap();
 
         assert_eq!(PartTwo::solve(&input).unwrap(), 208);
+
+        let mut mach = Machine::new(&input, MemV2::default());
+        mach.run().unwrap();
+        assert_eq!(mach.mem.sum_contents(), 208);
+    }
+
+    #[test]
+    fn masks() {
+        let mask = Mask::parse("XXXXXXXXXXXXXXXXXXXXXXXXXXXXX1XXXX0X").unwrap();
+        assert_eq!((mask.ones, mask.zeros), (0b1000000, 0b10));
+        assert_eq!(mask.floating, (1 << 36) - 1 - 0b1000010);
+
+        let err = Program::parse("mask = 1X0\nmem[8] = 11\nmask = 1X").unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "line 3: mask is 2 bits wide, but earlier masks are 3"
+        );
+        assert!(Mask::parse("").is_err());
+        assert!(Mask::parse(&"X".repeat(65)).is_err());
+        assert!(Mask::parse("XXXXXXXXXXXXXXXXXXXXXXXXXXXXX1XXXX2X").is_err());
+        assert!(Program::parse("mem[x] = 11").is_err());
+        assert!(Program::parse("reg[1] = 11").is_err());
+    }
+
+    #[test]
+    fn word_widths() {
+        let input = Program::parse("mask = 1X0X\nmem[15] = 15\nmem[16] = 1").unwrap();
+        let mut mach = Machine::new(&input, MemV1::default());
+        let err = mach.run().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "instruction 3: mem[16] = 1 does not fit in 4 bits"
+        );
+        assert_eq!(mach.mem.sum_contents(), 0b1101);
+        assert!(Machine::with_width(&input, 36, MemV1::default()).is_err());
+
+        let input = Program::parse("mem[40] = 64").unwrap();
+        assert!(Machine::with_width(&input, 6, MemV1::default())
+            .unwrap()
+            .run()
+            .is_err());
+        assert!(Machine::with_width(&input, 65, MemV1::default()).is_err());
+
+        let mask = "X".repeat(64);
+        let input = Program::parse(&format!("mask = {}\nmem[0] = {}", mask, u64::MAX)).unwrap();
+        let mut mach = Machine::new(&input, SymbolicMemV2::default());
+        mach.run().unwrap();
+        assert_eq!(mach.mem.sum_contents(), u128::from(u64::MAX) << 64);
+    }
+
+    #[test]
+    fn trace_and_dump() {
+        let input = Program::parse(indoc! {"
+            mem[3] = 9
+            mask = 0X1X
+            mem[1] = 7
+            mem[12] = 5
+        "})
+        .unwrap();
+
+        let mut mach = Machine::new(&input, SymbolicMemV2::default());
+        let mut trace = Vec::new();
+        mach.run_with(|step| trace.push(trace_line(step, 4, Radix::Binary)))
+            .unwrap();
+        assert_eq!(
+            trace,
+            vec![
+                "    1  mem[3] = 9  (mask none) wrote 1001 to 0011",
+                "    2  mask = 0X1X",
+                "    3  mem[1] = 7  (mask 0X1X) wrote 0111 to 0X1X",
+                "    4  mem[12] = 5  (mask 0X1X) wrote 0101 to 1X1X",
+            ]
+        );
+        let dump = mach.mem.contents().into_iter();
+        let dump = dump.map(|(addrs, val)| format!("{} {}", addrs.format(4, Radix::Hex), val));
+        assert_eq!(dump.collect::<Vec<_>>(), vec!["0X1X 7", "1X1X 5"]);
+
+        let mut mach = Machine::new(&input, MemV2::default());
+        mach.run().unwrap();
+        let dump = mach.mem.contents().into_iter();
+        let dump = dump.map(|(addrs, val)| format!("{} {}", addrs.format(4, Radix::Hex), val));
+        assert_eq!(
+            dump.collect::<Vec<_>>(),
+            vec!["2 7", "3 7", "6 7", "7 7", "a 5", "b 5", "e 5", "f 5"]
+        );
+
+        let mut mach = Machine::new(&input, MemV1::default());
+        mach.run().unwrap();
+        assert_eq!(
+            mach.mem.contents(),
+            vec![
+                (Pattern::single(1), 7),
+                (Pattern::single(3), 9),
+                (Pattern::single(12), 7)
+            ]
+        );
+    }
+
+    #[test]
+    fn symbolic_memory() {
+        let input = Program::parse(indoc! {"
+            mask = 0000000000000000000000000000X0X01X0X
+            mem[3] = 5
+            mem[40] = 7
+            mask = 00000000000000000000000000001XX0XX00
+            mem[1] = 11
+            mask = 000000000000000000000000000000000000
+            mem[8] = 13
+            mem[40] = 17
+        "})
+        .unwrap();
+        let mut expanded = Machine::new(&input, MemV2::default());
+        expanded.run().unwrap();
+        let mut symbolic = Machine::new(&input, SymbolicMemV2::default());
+        symbolic.run().unwrap();
+        assert_eq!(symbolic.mem.sum_contents(), expanded.mem.sum_contents());
+
+        let input = Program::parse(indoc! {"
+            mask = XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX
+            mem[0] = 1
+            mask = 1XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX0
+            mem[0] = 2
+            mask = 000000000000000000000000000000000000
+            mem[1] = 0
+        "})
+        .unwrap();
+        let mut symbolic = Machine::new(&input, SymbolicMemV2::default());
+        symbolic.run().unwrap();
+        assert_eq!(symbolic.mem.sum_contents(), (1 << 36) + (1 << 34) - 1);
     }
 }
 
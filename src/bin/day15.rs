@@ -1,4 +1,9 @@
+use anyhow::{anyhow, bail, ensure};
 use aoc::{Parse, Result, Solve};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
 
 #[derive(Debug)]
 struct MemoryGame {
@@ -8,27 +13,200 @@ struct MemoryGame {
 impl<'a> Parse<'a> for MemoryGame {
     fn parse<'b: 'a>(input_str: &'b str) -> Result<Self> {
         let start = input_str
+            .trim()
             .split(',')
-            .map(str::parse)
-            .collect::<Result<_, _>>()?;
+            .map(|num| num.trim().parse())
+            .collect::<Result<Vec<_>, _>>()?;
+        ensure!(!start.is_empty(), "no starting numbers");
         Ok(MemoryGame { start })
     }
 }
 
+/// Remembers the turn on which each number was last spoken.
+trait Storage {
+    /// Records that `num` was spoken on `turn`, returning the turn on which
+    /// it was last spoken before that, if ever.
+    fn replace(&mut self, num: u32, turn: u32) -> Option<u32>;
+}
+
+impl Storage for HashMap<u32, u32> {
+    fn replace(&mut self, num: u32, turn: u32) -> Option<u32> {
+        self.insert(num, turn)
+    }
+}
+
+/// Storage that keeps numbers below a threshold in a flat array, which is
+/// fast but costs memory for every number up to the threshold, and the rest
+/// in a hash map.
+#[derive(Debug)]
+struct HybridStorage {
+    dense: Vec<u32>,
+    sparse: HashMap<u32, u32>,
+}
+
+impl HybridStorage {
+    /// Marks a number that has not been spoken in `dense`. No game lasts long
+    /// enough to reach this turn.
+    const NEVER: u32 = u32::MAX;
+
+    fn new(dense_len: usize) -> Self {
+        HybridStorage {
+            dense: vec![Self::NEVER; dense_len],
+            sparse: HashMap::new(),
+        }
+    }
+}
+
+impl Storage for HybridStorage {
+    fn replace(&mut self, num: u32, turn: u32) -> Option<u32> {
+        match self.dense.get_mut(num as usize) {
+            Some(slot) => match std::mem::replace(slot, turn) {
+                Self::NEVER => None,
+                prev => Some(prev),
+            },
+            None => self.sparse.insert(num, turn),
+        }
+    }
+}
+
+/// The numbers spoken in a game, starting with the first turn.
+#[derive(Debug)]
+struct Spoken<'a, S> {
+    start: &'a [u32],
+    storage: S,
+    turn: u32,
+    last: u32,
+}
+
+impl<S: Storage> Iterator for Spoken<'_, S> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        let turn = self.turn;
+        if turn == u32::MAX {
+            return None;
+        }
+        let num = match self.start.get(turn as usize) {
+            Some(&num) if turn == 0 => num,
+            start => {
+                let prev = self.storage.replace(self.last, turn - 1);
+                match start {
+                    Some(&num) => num,
+                    None => prev.map_or(0, |prev| turn - 1 - prev),
+                }
+            }
+        };
+        self.turn += 1;
+        self.last = num;
+        Some(num)
+    }
+}
+
 impl MemoryGame {
-    fn nth(&self, n: usize) -> u32 {
-        let mut said = vec![u32::MAX; n];
-        for (turn, &num) in self.start.iter().enumerate() {
-            said[num as usize] = turn as u32;
+    /// The most numbers to track in an array, which takes 128 MiB. Longer
+    /// games keep larger numbers in a hash map instead.
+    const DENSE_LIMIT: usize = 1 << 25;
+
+    /// The most turns a game can last before its turn counter runs out.
+    const MAX_TURNS: usize = u32::MAX as usize;
+
+    /// The numbers spoken in a game, keeping track of them in `storage`.
+    fn spoken_with<S: Storage>(&self, storage: S) -> Spoken<'_, S> {
+        Spoken {
+            start: &self.start,
+            storage,
+            turn: 0,
+            last: 0,
         }
-        let mut last = self.start[self.start.len() - 1];
-        for turn in self.start.len()..n {
-            let turn = turn as u32;
-            let mut prev = turn - 1;
-            std::mem::swap(&mut prev, &mut said[last as usize]);
-            last = (turn - 1).saturating_sub(prev);
+    }
+
+    /// The numbers spoken in a game, tracking the first `dense_len` numbers
+    /// in an array.
+    fn spoken(&self, dense_len: usize) -> Spoken<'_, HybridStorage> {
+        self.spoken_with(HybridStorage::new(dense_len))
+    }
+
+    /// How many numbers to track in an array for a game that lasts `turns`
+    /// turns: as many as could be spoken, up to a limit.
+    fn dense_len(turns: usize) -> usize {
+        // Every number spoken after the starting numbers is the gap between
+        // two earlier turns, so is less than `turns`.
+        turns.min(Self::DENSE_LIMIT)
+    }
+
+    /// The numbers spoken in a game that lasts `turns` turns.
+    fn spoken_for(&self, turns: usize) -> Spoken<'_, HybridStorage> {
+        self.spoken(Self::dense_len(turns))
+    }
+
+    /// Returns the number spoken on the `n`th turn, counting from 1.
+    fn nth(&self, n: usize) -> Option<u32> {
+        if n > Self::MAX_TURNS {
+            return None;
+        }
+        self.spoken_for(n).nth(n.checked_sub(1)?)
+    }
+
+    /// Summarises the numbers spoken in the first `turns` turns, or in as
+    /// many as the game lasts.
+    fn stats(&self, turns: usize) -> Stats {
+        let turns = turns.min(Self::MAX_TURNS);
+        let mut stats = Stats::default();
+        let mut last_seen = HashMap::new();
+        for (turn, num) in (1..).zip(self.spoken_for(turns).take(turns)) {
+            stats.turns = turn;
+            if num == 0 {
+                stats.zeros += 1;
+            }
+            match last_seen.insert(num, turn) {
+                Some(prev) => {
+                    let gap = turn - prev;
+                    if stats.largest_gap.is_none_or(|(largest, _)| gap > largest) {
+                        stats.largest_gap = Some((gap, num));
+                    }
+                }
+                None => {
+                    stats.first_seen.insert(num, turn);
+                }
+            }
+        }
+        stats
+    }
+
+    /// Writes the first `turns` numbers spoken as CSV, one row per turn, or
+    /// as many as the game lasts.
+    fn write_csv(&self, turns: usize, mut out: impl Write) -> io::Result<()> {
+        let turns = turns.min(Self::MAX_TURNS);
+        writeln!(out, "turn,number")?;
+        for (turn, num) in (1..).zip(self.spoken_for(turns).take(turns)) {
+            writeln!(out, "{},{}", turn, num)?;
+        }
+        out.flush()
+    }
+}
+
+#[derive(Debug, Default)]
+struct Stats {
+    turns: usize,
+    /// How many times zero was spoken.
+    zeros: usize,
+    /// The most turns between two times the same number was spoken, and
+    /// the first number to be repeated after that many turns.
+    largest_gap: Option<(usize, u32)>,
+    /// The turn on which each number was first spoken.
+    first_seen: BTreeMap<u32, usize>,
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "turns: {}", self.turns)?;
+        writeln!(f, "distinct numbers: {}", self.first_seen.len())?;
+        match self.largest_gap {
+            Some((gap, num)) => writeln!(f, "largest gap: {} turns, for {}", gap, num)?,
+            None => writeln!(f, "largest gap: none")?,
         }
-        last
+        let share = self.zeros as f64 / self.turns.max(1) as f64;
+        writeln!(f, "zeros: {} ({:.2}%)", self.zeros, 100.0 * share)
     }
 }
 
@@ -39,7 +217,7 @@ impl Solve<'_> for PartOne {
     type Solution = u32;
 
     fn solve(input: &Self::Input) -> Result<Self::Solution> {
-        Ok(input.nth(2020))
+        input.nth(2020).ok_or_else(|| anyhow!("game ended early"))
     }
 }
 
@@ -50,15 +228,41 @@ impl Solve<'_> for PartTwo {
     type Solution = u32;
 
     fn solve(input: &Self::Input) -> Result<Self::Solution> {
-        Ok(input.nth(30_000_000))
+        input
+            .nth(30_000_000)
+            .ok_or_else(|| anyhow!("game ended early"))
+    }
+}
+
+const USAGE: &str = "usage: day15 stats TURNS
+       day15 first TURNS
+       day15 csv TURNS PATH";
+
+fn cli(input_str: &str, args: &[String]) -> Result<()> {
+    let game = MemoryGame::parse(input_str)?;
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    match args[..] {
+        ["stats", turns] => print!("{}", game.stats(turns.parse()?)),
+        ["first", turns] => {
+            for (num, turn) in game.stats(turns.parse()?).first_seen {
+                println!("{:>10}  {}", num, turn);
+            }
+        }
+        ["csv", turns, path] => {
+            let file = BufWriter::new(File::create(path)?);
+            game.write_csv(turns.parse()?, file)?;
+        }
+        _ => bail!("unexpected arguments {:?}\n{}", args, USAGE),
     }
+    Ok(())
 }
 
-aoc::main!(day15);
+aoc::main!(day15, cli = cli);
 
 #[cfg(test)]
 mod examples {
     use super::*;
+    use indoc::indoc;
 
     #[test]
     fn example() {
@@ -72,38 +276,93 @@ mod examples {
         let game = MemoryGame {
             start: vec![1, 3, 2],
         };
-        assert_eq!(game.nth(2020), 1);
-        assert_eq!(game.nth(30_000_000), 2578);
+        assert_eq!(game.nth(2020), Some(1));
+        assert_eq!(game.nth(30_000_000), Some(2578));
 
         let game = MemoryGame {
             start: vec![2, 1, 3],
         };
-        assert_eq!(game.nth(2020), 10);
-        assert_eq!(game.nth(30_000_000), 3544142);
+        assert_eq!(game.nth(2020), Some(10));
+        assert_eq!(game.nth(30_000_000), Some(3544142));
 
         let game = MemoryGame {
             start: vec![1, 2, 3],
         };
-        assert_eq!(game.nth(2020), 27);
-        assert_eq!(game.nth(30_000_000), 261214);
+        assert_eq!(game.nth(2020), Some(27));
+        assert_eq!(game.nth(30_000_000), Some(261214));
 
         let game = MemoryGame {
             start: vec![2, 3, 1],
         };
-        assert_eq!(game.nth(2020), 78);
-        assert_eq!(game.nth(30_000_000), 6895259);
+        assert_eq!(game.nth(2020), Some(78));
+        assert_eq!(game.nth(30_000_000), Some(6895259));
 
         let game = MemoryGame {
             start: vec![3, 2, 1],
         };
-        assert_eq!(game.nth(2020), 438);
-        assert_eq!(game.nth(30_000_000), 18);
+        assert_eq!(game.nth(2020), Some(438));
+        assert_eq!(game.nth(30_000_000), Some(18));
 
         let game = MemoryGame {
             start: vec![3, 1, 2],
         };
-        assert_eq!(game.nth(2020), 1836);
-        assert_eq!(game.nth(30_000_000), 362);
+        assert_eq!(game.nth(2020), Some(1836));
+        assert_eq!(game.nth(30_000_000), Some(362));
+    }
+
+    #[test]
+    fn arbitrary_starts() {
+        let game = MemoryGame::parse("0,3,6").unwrap();
+        let spoken = game.spoken(4).take(10).collect::<Vec<_>>();
+        assert_eq!(spoken, vec![0, 3, 6, 0, 3, 3, 1, 0, 4, 0]);
+        let spoken = game.spoken_with(HashMap::new()).take(10);
+        assert_eq!(
+            spoken.collect::<Vec<_>>(),
+            vec![0, 3, 6, 0, 3, 3, 1, 0, 4, 0]
+        );
+
+        let game = MemoryGame::parse("100000,7,100000").unwrap();
+        let spoken = game.spoken(8).take(7).collect::<Vec<_>>();
+        assert_eq!(spoken, vec![100000, 7, 100000, 2, 0, 0, 1]);
+        assert_eq!(game.nth(3), Some(100000));
+        assert_eq!(game.nth(0), None);
+        assert_eq!(game.nth(usize::MAX), None);
+        assert_eq!(game.spoken_for(10).storage.dense.len(), 10);
+        assert_eq!(MemoryGame::dense_len(usize::MAX), MemoryGame::DENSE_LIMIT);
+
+        let game = MemoryGame::parse("4000000000").unwrap();
+        assert_eq!(game.nth(4), Some(1));
+        assert_eq!(game.nth(5), Some(0));
+
+        assert!(MemoryGame::parse("").is_err());
+        assert!(MemoryGame::parse("1,,2").is_err());
+    }
+
+    #[test]
+    fn analysis() {
+        let game = MemoryGame::parse("0,3,6").unwrap();
+        let stats = game.stats(10);
+        assert_eq!(stats.zeros, 4);
+        assert_eq!(stats.largest_gap, Some((4, 0)));
+        let first_seen = stats.first_seen.into_iter().collect::<Vec<_>>();
+        assert_eq!(first_seen, vec![(0, 1), (1, 7), (3, 2), (4, 9), (6, 3)]);
+
+        assert_eq!(
+            game.stats(4).to_string(),
+            indoc! {"
+                turns: 4
+                distinct numbers: 3
+                largest gap: 3 turns, for 0
+                zeros: 2 (50.00%)
+            "}
+        );
+
+        let mut csv = Vec::new();
+        game.write_csv(4, &mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "turn,number\n1,0\n2,3\n3,6\n4,0\n"
+        );
     }
 }
 
@@ -1,52 +1,123 @@
-use aoc::{Parse, Result, Solve};
-use range_collections::RangeSet;
-use std::collections::HashMap;
+use anyhow::{anyhow, bail, ensure};
+use aoc::{interval::IntervalSet, Context, Parse, Result, Solve};
+use std::collections::BTreeSet;
+use std::fmt::Write;
 use std::ops::Range;
 
+struct Field<'a> {
+    name: &'a str,
+    ranges: Vec<Range<u32>>,
+}
+
+impl Field<'_> {
+    fn contains(&self, value: u32) -> bool {
+        self.ranges.iter().any(|range| range.contains(&value))
+    }
+}
+
+/// Parses an inclusive range such as `25-974`.
+fn parse_range(range: &str) -> Result<Range<u32>> {
+    let mut bounds = range.splitn(2, '-');
+    let (low, high) = match (bounds.next(), bounds.next()) {
+        (Some(low), Some(high)) => (low.trim(), high.trim()),
+        _ => bail!("invalid range {:?}", range),
+    };
+    let low = low
+        .parse::<u32>()
+        .with_context(|| format!("invalid range {:?}", range))?;
+    let high = high
+        .parse::<u32>()
+        .with_context(|| format!("invalid range {:?}", range))?;
+    ensure!(low <= high, "inverted range {:?}", range);
+    let end = high
+        .checked_add(1)
+        .ok_or_else(|| anyhow!("range {:?} is too large", range))?;
+    Ok(low..end)
+}
+
+impl<'a> Parse<'a> for Field<'a> {
+    fn parse<'b: 'a>(input_str: &'b str) -> Result<Self> {
+        let mut split = input_str.splitn(2, ':');
+        let (name, ranges) = match (split.next(), split.next()) {
+            (Some(name), Some(ranges)) if !name.is_empty() => (name, ranges.trim()),
+            _ => bail!("invalid field rule {:?}", input_str),
+        };
+        let ranges = ranges
+            .split(" or ")
+            .map(parse_range)
+            .collect::<Result<_>>()
+            .with_context(|| format!("in rule for field {:?}", name))?;
+        Ok(Field { name, ranges })
+    }
+}
+
 struct Input<'a> {
-    fields: HashMap<&'a str, (Range<u32>, Range<u32>)>,
+    fields: Vec<Field<'a>>,
     mine: Vec<u32>,
     nearby: Vec<Vec<u32>>,
 }
 
+/// Parses a comma-separated ticket, which must have one value per field.
+fn parse_ticket(line: &str, fields: usize) -> Result<Vec<u32>> {
+    let values = line
+        .split(',')
+        .map(|value| value.trim().parse::<u32>())
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("invalid ticket {:?}", line))?;
+    ensure!(
+        values.len() == fields,
+        "expected {} values but found {}",
+        fields,
+        values.len()
+    );
+    Ok(values)
+}
+
 impl<'a> Parse<'a> for Input<'a> {
     fn parse<'b: 'a>(input_str: &'b str) -> Result<Self> {
-        let mut groups = input_str.split("\n\n");
-        let fields = groups
-            .next()
-            .unwrap()
+        let mut lines = input_str
             .lines()
-            .map(|line| {
-                let mut split = line.splitn(2, ": ");
-                let field = split.next().unwrap();
-                let ranges = split.next().unwrap().splitn(2, " or ");
-                let mut ranges = ranges.map(|range| {
-                    let mut bounds = range.split("-");
-                    let low = bounds.next().unwrap().parse::<u32>().unwrap();
-                    let hi = bounds.next().unwrap().parse::<u32>().unwrap();
-                    low..(hi + 1)
-                });
-                (field, (ranges.next().unwrap(), ranges.next().unwrap()))
-            })
-            .collect();
+            .map(str::trim)
+            .enumerate()
+            .filter(|(_, line)| !line.is_empty())
+            .map(|(index, line)| (index + 1, line));
 
-        let mine = groups
-            .next()
-            .unwrap()
-            .lines()
-            .nth(1)
-            .unwrap()
-            .split(",")
-            .map(|num| num.parse().unwrap())
-            .collect();
-
-        let nearby = groups
-            .next()
-            .unwrap()
-            .lines()
-            .skip(1)
-            .map(|line| line.split(",").map(|num| num.parse().unwrap()).collect())
-            .collect();
+        let mut fields = Vec::new();
+        loop {
+            match lines.next() {
+                Some((_, "your ticket:")) => break,
+                Some((_, "nearby tickets:")) | None => {
+                    bail!("missing \"your ticket:\" section")
+                }
+                Some((number, line)) => {
+                    let field = Field::parse(line).with_context(|| format!("line {}", number))?;
+                    fields.push(field);
+                }
+            }
+        }
+
+        let mine = match lines.next() {
+            Some((number, line)) if line != "nearby tickets:" => {
+                parse_ticket(line, fields.len()).with_context(|| format!("line {}", number))?
+            }
+            _ => bail!("missing ticket after \"your ticket:\""),
+        };
+
+        match lines.next() {
+            Some((_, "nearby tickets:")) => {}
+            Some((number, line)) => bail!(
+                "line {}: expected \"nearby tickets:\", found {:?}",
+                number,
+                line
+            ),
+            None => bail!("missing \"nearby tickets:\" section"),
+        }
+
+        let nearby = lines
+            .map(|(number, line)| {
+                parse_ticket(line, fields.len()).with_context(|| format!("line {}", number))
+            })
+            .collect::<Result<_>>()?;
 
         Ok(Input {
             fields,
@@ -56,6 +127,120 @@ impl<'a> Parse<'a> for Input<'a> {
     }
 }
 
+impl<'a> Input<'a> {
+    /// Returns the values that are valid for at least one field.
+    fn valid_values(&self) -> IntervalSet<u32> {
+        let ranges = self.fields.iter().flat_map(|field| &field.ranges);
+        ranges.cloned().collect()
+    }
+
+    /// Returns the nearby tickets whose values are all valid for some field.
+    fn valid_tickets(&self) -> Vec<&[u32]> {
+        let ranges = self.valid_values();
+        self.nearby
+            .iter()
+            .filter(|ticket| ticket.iter().all(|value| ranges.contains(value)))
+            .map(Vec::as_slice)
+            .collect()
+    }
+
+    /// For each ticket column, returns the indices of the fields that every
+    /// one of `tickets` has a valid value for in that column.
+    fn candidates(&self, tickets: &[&[u32]]) -> Vec<BTreeSet<usize>> {
+        (0..self.fields.len())
+            .map(|col| {
+                (0..self.fields.len())
+                    .filter(|&field| {
+                        let field = &self.fields[field];
+                        tickets.iter().all(|ticket| field.contains(ticket[col]))
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// Repeatedly removes the field of every column with a single candidate from
+/// all the other columns' candidates.
+fn eliminate(candidates: &mut [BTreeSet<usize>]) {
+    let mut settled = vec![false; candidates.len()];
+    while let Some(col) =
+        (0..candidates.len()).find(|&col| !settled[col] && candidates[col].len() == 1)
+    {
+        settled[col] = true;
+        let field = *candidates[col].iter().next().unwrap();
+        for (other, set) in candidates.iter_mut().enumerate() {
+            if other != col {
+                set.remove(&field);
+            }
+        }
+    }
+}
+
+/// Finds a perfect matching of columns to candidate fields using augmenting
+/// paths, skipping the column-to-field edge `forbidden` if given. Returns the
+/// field matched to each column.
+fn match_fields(
+    candidates: &[BTreeSet<usize>],
+    forbidden: Option<(usize, usize)>,
+) -> Option<Vec<usize>> {
+    fn augment(
+        candidates: &[BTreeSet<usize>],
+        forbidden: Option<(usize, usize)>,
+        col: usize,
+        visited: &mut [bool],
+        owner: &mut [Option<usize>],
+    ) -> bool {
+        for &field in &candidates[col] {
+            if forbidden == Some((col, field)) || visited[field] {
+                continue;
+            }
+            visited[field] = true;
+            let free = match owner[field] {
+                None => true,
+                Some(other) => augment(candidates, forbidden, other, visited, owner),
+            };
+            if free {
+                owner[field] = Some(col);
+                return true;
+            }
+        }
+        false
+    }
+
+    let mut owner = vec![None; candidates.len()];
+    for col in 0..candidates.len() {
+        let mut visited = vec![false; candidates.len()];
+        if !augment(candidates, forbidden, col, &mut visited, &mut owner) {
+            return None;
+        }
+    }
+    let mut assignment = vec![0; candidates.len()];
+    for (field, col) in owner.into_iter().enumerate() {
+        assignment[col?] = field;
+    }
+    Some(assignment)
+}
+
+/// Assigns a distinct field to every column, failing unless there is exactly
+/// one way to do so.
+fn assign(candidates: &[BTreeSet<usize>]) -> Result<Vec<usize>> {
+    let mut candidates = candidates.to_vec();
+    eliminate(&mut candidates);
+    let assignment = match match_fields(&candidates, None) {
+        Some(assignment) => assignment,
+        None => bail!("no assignment of fields to columns is possible"),
+    };
+    for (col, &field) in assignment.iter().enumerate() {
+        ensure!(
+            match_fields(&candidates, Some((col, field))).is_none(),
+            "column {} could be more than one field",
+            col
+        );
+    }
+    Ok(assignment)
+}
+
 struct PartOne;
 
 impl<'a> Solve<'a> for PartOne {
@@ -63,15 +248,11 @@ impl<'a> Solve<'a> for PartOne {
     type Solution = u32;
 
     fn solve(input: &Self::Input) -> Result<Self::Solution> {
-        let mut ranges = RangeSet::<u32>::empty();
-        for (r1, r2) in input.fields.values() {
-            ranges |= RangeSet::from(r1.start..r1.end);
-            ranges |= RangeSet::from(r2.start..r2.end);
-        }
+        let ranges = input.valid_values();
         let mut sum = 0;
         for other in input.nearby.iter() {
             for value in other {
-                if !ranges.contains(&value) {
+                if !ranges.contains(value) {
                     sum += value;
                 }
             }
@@ -84,49 +265,103 @@ struct PartTwo;
 
 impl<'a> Solve<'a> for PartTwo {
     type Input = Input<'a>;
-    type Solution = u32;
+    type Solution = u64;
 
     fn solve(input: &Self::Input) -> Result<Self::Solution> {
-        let mut ranges = RangeSet::<u32>::empty();
-        for (r1, r2) in input.fields.values() {
-            ranges |= RangeSet::from(r1.start..r1.end);
-            ranges |= RangeSet::from(r2.start..r2.end);
-        }
-        let mut valid = Vec::new();
-        for other in input.nearby.iter() {
-            if other.iter().all(|value| ranges.contains(&value)) {
-                valid.push(other);
+        let candidates = input.candidates(&input.valid_tickets());
+        let assignment = assign(&candidates)?;
+
+        let mut prod = 1;
+        for (&field, &val) in assignment.iter().zip(input.mine.iter()) {
+            if input.fields[field].name.starts_with("departure") {
+                prod *= u64::from(val);
             }
         }
 
-        use itertools::Itertools;
+        Ok(prod)
+    }
+}
+
+/// Formats each column's candidate fields by name.
+fn write_candidates(
+    report: &mut String,
+    input: &Input,
+    candidates: &[BTreeSet<usize>],
+) -> std::fmt::Result {
+    for (col, set) in candidates.iter().enumerate() {
+        let names = set.iter().map(|&field| input.fields[field].name);
+        writeln!(
+            report,
+            "  {:>3}: {}",
+            col,
+            names.collect::<Vec<_>>().join(", ")
+        )?;
+    }
+    Ok(())
+}
 
-        let order = input
-            .fields
+/// Describes how the answer to part two is reached: which nearby tickets are
+/// thrown out and why, what each column could be before and after
+/// elimination, and the final mapping applied to your ticket.
+fn report(input: &Input) -> Result<String> {
+    let mut report = String::new();
+    let ranges = input.valid_values();
+
+    writeln!(report, "invalid nearby tickets:")?;
+    for (index, ticket) in input.nearby.iter().enumerate() {
+        let invalid = ticket
             .iter()
-            .permutations(input.fields.len())
-            .find(|order| {
-                input.nearby.iter().all(|nearby| {
-                    nearby
-                        .iter()
-                        .zip(order)
-                        .all(|(val, (_, (r1, r2)))| r1.contains(&val) || r2.contains(&val))
-                })
-            })
-            .unwrap();
+            .filter(|value| !ranges.contains(value))
+            .map(u32::to_string)
+            .collect::<Vec<_>>();
+        if !invalid.is_empty() {
+            writeln!(report, "  {:>3}: {}", index, invalid.join(", "))?;
+        }
+    }
 
-        let mut prod = 1;
-        for ((key, _), val) in order.iter().zip(input.mine.iter()) {
-            if key.starts_with("departure") {
-                prod *= val;
-            }
+    let mut candidates = input.candidates(&input.valid_tickets());
+    writeln!(report, "\ncandidates:")?;
+    write_candidates(&mut report, input, &candidates)?;
+    eliminate(&mut candidates);
+    writeln!(report, "\ncandidates after elimination:")?;
+    write_candidates(&mut report, input, &candidates)?;
+
+    let assignment = match assign(&candidates) {
+        Ok(assignment) => assignment,
+        Err(err) => {
+            writeln!(report, "\nassignment failed: {}", err)?;
+            return Ok(report);
         }
+    };
+    let width = input.fields.iter().map(|field| field.name.len()).max();
+    let width = width.unwrap_or_default().max("field".len());
+    writeln!(report, "\ncol  {:<width$}  value", "field", width = width)?;
+    for (col, (&field, value)) in assignment.iter().zip(&input.mine).enumerate() {
+        let name = input.fields[field].name;
+        writeln!(
+            report,
+            "{:>3}  {:<width$}  {:>5}",
+            col,
+            name,
+            value,
+            width = width
+        )?;
+    }
+    Ok(report)
+}
 
-        Ok(prod)
+const USAGE: &str = "usage: day16 report";
+
+fn cli(input_str: &str, args: &[String]) -> Result<()> {
+    let input = Input::parse(input_str)?;
+    match args {
+        [command] if command == "report" => print!("{}", report(&input)?),
+        _ => bail!("{}", USAGE),
     }
+    Ok(())
 }
 
-aoc::main!(day16);
+aoc::main!(day16, cli = cli);
 
 #[cfg(test)]
 mod examples {
@@ -152,6 +387,168 @@ mod examples {
         .unwrap();
 
         assert_eq!(PartOne::solve(&input).unwrap(), 71);
-        assert_eq!(PartTwo::solve(&input).unwrap(), 0);
+        assert_eq!(input.valid_tickets(), vec![&[7, 3, 47]]);
+    }
+
+    #[test]
+    fn example_part_two() {
+        let input = Input::parse(indoc! {"
+            class: 0-1 or 4-19
+            row: 0-5 or 8-19
+            seat: 0-13 or 16-19
+
+            your ticket:
+            11,12,13
+
+            nearby tickets:
+            3,9,18
+            15,1,5
+            5,14,9
+        "})
+        .unwrap();
+
+        let candidates = input.candidates(&input.valid_tickets());
+        let assignment = assign(&candidates).unwrap();
+        let names = assignment.iter().map(|&field| input.fields[field].name);
+        assert_eq!(names.collect::<Vec<_>>(), vec!["row", "class", "seat"]);
+        assert_eq!(PartTwo::solve(&input).unwrap(), 1);
+    }
+
+    #[test]
+    fn example_report() {
+        let input = Input::parse(indoc! {"
+            class: 0-1 or 4-19
+            row: 0-5 or 8-19
+            seat: 0-13 or 16-19
+
+            your ticket:
+            11,12,13
+
+            nearby tickets:
+            3,9,18
+            20,1,5
+            15,1,5
+            5,14,9
+        "})
+        .unwrap();
+
+        assert_eq!(
+            report(&input).unwrap(),
+            indoc! {"
+                invalid nearby tickets:
+                    1: 20
+
+                candidates:
+                    0: row
+                    1: class, row
+                    2: class, row, seat
+
+                candidates after elimination:
+                    0: row
+                    1: class
+                    2: seat
+
+                col  field  value
+                  0  row       11
+                  1  class     12
+                  2  seat      13
+            "}
+        );
+
+        let input = Input::parse(indoc! {"
+            class: 0-9
+            row: 0-9
+
+            your ticket:
+            1,2
+
+            nearby tickets:
+            3,4
+        "})
+        .unwrap();
+
+        assert_eq!(
+            report(&input).unwrap(),
+            indoc! {"
+                invalid nearby tickets:
+
+                candidates:
+                    0: class, row
+                    1: class, row
+
+                candidates after elimination:
+                    0: class, row
+                    1: class, row
+
+                assignment failed: column 0 could be more than one field
+            "}
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        fn error(input_str: &str) -> String {
+            format!("{:#}", Input::parse(input_str).err().unwrap())
+        }
+
+        let input = Input::parse(indoc! {"
+            class: 1-3 or 5-7 or 9-9
+            row: 6-11
+
+            your ticket:
+            7,1
+
+            nearby tickets:
+            9,6
+        "})
+        .unwrap();
+        assert_eq!(input.fields[0].ranges, vec![1..4, 5..8, 9..10]);
+        assert_eq!(input.fields[1].ranges, vec![6..12]);
+
+        assert_eq!(
+            error("class 1-3\nyour ticket:\n1\nnearby tickets:\n"),
+            "line 1: invalid field rule \"class 1-3\""
+        );
+        assert_eq!(
+            error("class: 3-1\nyour ticket:\n1\nnearby tickets:\n"),
+            "line 1: in rule for field \"class\": inverted range \"3-1\""
+        );
+        assert_eq!(
+            error("class: 1-3\n\nnearby tickets:\n1\n"),
+            "missing \"your ticket:\" section"
+        );
+        assert_eq!(
+            error("class: 1-3\nyour ticket:\n1\n"),
+            "missing \"nearby tickets:\" section"
+        );
+        assert_eq!(
+            error("class: 1-3\nyour ticket:\n1\nnearby tickets:\n1\n1,2\n"),
+            "line 6: expected 1 values but found 2"
+        );
+    }
+
+    #[test]
+    fn ambiguous_or_impossible() {
+        let ambiguous = vec![[0, 1].iter().copied().collect(); 2];
+        assert!(assign(&ambiguous).is_err());
+
+        let impossible = vec![[0].iter().copied().collect(); 2];
+        assert!(assign(&impossible).is_err());
+
+        let ambiguous = vec![
+            [0, 1, 2].iter().copied().collect(),
+            [0, 1].iter().copied().collect(),
+            [1, 2].iter().copied().collect(),
+        ];
+        assert!(assign(&ambiguous).is_err());
+
+        let unique = vec![
+            [0, 1].iter().copied().collect(),
+            [1, 2].iter().copied().collect(),
+            [2].iter().copied().collect(),
+        ];
+        assert_eq!(assign(&unique).unwrap(), vec![0, 1, 2]);
     }
 }
+
+aoc::solved!(day16, PartOne = 23115, PartTwo = 239727793813);
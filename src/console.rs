@@ -0,0 +1,390 @@
+//! The handheld game console's boot code interpreter.
+
+use crate::{Parse, Result};
+use anyhow::{anyhow, bail};
+use std::{convert::TryFrom, fmt};
+
+pub mod asm;
+pub mod cfg;
+pub mod debugger;
+
+/// An operation the console knows how to perform.
+///
+/// Executing an opcode may update the accumulator, then moves the program
+/// counter by an offset that depends only on the argument. Keeping the two
+/// apart lets a program's control flow be worked out without running it.
+#[derive(Copy, Clone)]
+pub struct Opcode {
+    pub mnemonic: &'static str,
+    /// Updates the accumulator.
+    pub exec: fn(accum: &mut isize, arg: isize),
+    /// Returns the offset to add to the program counter to reach the next
+    /// instruction.
+    pub offset: fn(arg: isize) -> isize,
+}
+
+impl PartialEq for Opcode {
+    fn eq(&self, other: &Self) -> bool {
+        self.mnemonic == other.mnemonic
+    }
+}
+
+impl Eq for Opcode {}
+
+impl fmt::Debug for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.mnemonic)
+    }
+}
+
+/// Adds the argument to the accumulator.
+pub const ACC: Opcode = Opcode {
+    mnemonic: "acc",
+    exec: |accum, arg| *accum += arg,
+    offset: |_| 1,
+};
+
+/// Jumps to a new instruction relative to itself.
+pub const JMP: Opcode = Opcode {
+    mnemonic: "jmp",
+    exec: |_, _| {},
+    offset: |arg| arg,
+};
+
+/// Does nothing.
+pub const NOP: Opcode = Opcode {
+    mnemonic: "nop",
+    exec: |_, _| {},
+    offset: |_| 1,
+};
+
+/// The opcodes a program may be written with.
+#[derive(Clone, Debug)]
+pub struct InstructionSet {
+    opcodes: Vec<Opcode>,
+}
+
+impl Default for InstructionSet {
+    fn default() -> Self {
+        InstructionSet {
+            opcodes: vec![ACC, JMP, NOP],
+        }
+    }
+}
+
+impl InstructionSet {
+    /// Adds an opcode to the set, replacing any with the same mnemonic.
+    pub fn with(mut self, opcode: Opcode) -> Self {
+        self.opcodes.retain(|op| op.mnemonic != opcode.mnemonic);
+        self.opcodes.push(opcode);
+        self
+    }
+
+    pub fn opcode(&self, mnemonic: &str) -> Option<Opcode> {
+        self.opcodes
+            .iter()
+            .copied()
+            .find(|op| op.mnemonic == mnemonic)
+    }
+
+    /// Parses an instruction such as `jmp -3`.
+    pub fn parse_instr(&self, line: &str) -> Result<Instr> {
+        let mut split = line.splitn(2, ' ');
+        let (mnemonic, arg) = match (split.next(), split.next()) {
+            (Some(mnemonic), Some(arg)) => (mnemonic, arg),
+            _ => bail!("could not split instruction: {:?}", line),
+        };
+        let opcode = self
+            .opcode(mnemonic)
+            .ok_or_else(|| anyhow!("unknown opcode {:?}", mnemonic))?;
+        let arg = arg.parse()?;
+        Ok(Instr { opcode, arg })
+    }
+
+    /// Parses a program with one instruction per line.
+    pub fn parse_program(&self, input_str: &str) -> Result<Program> {
+        let lines = input_str.lines().map(str::trim);
+        let instrs = lines
+            .map(|line| self.parse_instr(line))
+            .collect::<Result<_>>()?;
+        Ok(Program { instrs })
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Instr {
+    pub opcode: Opcode,
+    pub arg: isize,
+}
+
+impl fmt::Display for Instr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {:+}", self.opcode.mnemonic, self.arg)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Program {
+    pub instrs: Vec<Instr>,
+}
+
+impl<'a> Parse<'a> for Program {
+    fn parse<'b: 'a>(input_str: &'b str) -> Result<Self> {
+        InstructionSet::default().parse_program(input_str)
+    }
+}
+
+impl Instr {
+    /// Returns the index the program counter moves to after running this
+    /// instruction at `pc`, which may lie outside the program.
+    pub fn target(&self, pc: usize) -> isize {
+        (pc as isize).saturating_add((self.opcode.offset)(self.arg))
+    }
+
+    /// Returns the index of the instruction that runs after this one, if this
+    /// is at `pc` and control stays within or just past a program of `len`
+    /// instructions.
+    fn successor(&self, pc: usize, len: usize) -> Option<usize> {
+        let next = usize::try_from(self.target(pc)).ok()?;
+        if next <= len {
+            Some(next)
+        } else {
+            None
+        }
+    }
+
+    /// Swaps a `jmp` for a `nop` or vice versa, keeping the argument.
+    pub fn flipped(&self) -> Option<Instr> {
+        let opcode = if self.opcode == JMP {
+            NOP
+        } else if self.opcode == NOP {
+            JMP
+        } else {
+            return None;
+        };
+        Some(Instr { opcode, ..*self })
+    }
+}
+
+/// A change to a single instruction of a program.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Patch {
+    pub index: usize,
+    pub before: Instr,
+    pub after: Instr,
+}
+
+impl fmt::Display for Patch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {} -> {}", self.index, self.before, self.after)
+    }
+}
+
+impl Program {
+    pub fn patched(&self, patch: &Patch) -> Program {
+        let mut program = self.clone();
+        program.instrs[patch.index] = patch.after;
+        program
+    }
+
+    /// Finds the single `jmp`/`nop` swap that makes a looping program halt.
+    ///
+    /// First works backwards from the end of the program to find every
+    /// instruction from which it halts, then walks the original execution
+    /// once, stopping at the first instruction whose swap leads into that
+    /// set. This is linear in the length of the program.
+    pub fn find_repair(&self) -> Option<Patch> {
+        let len = self.instrs.len();
+        let mut preds = vec![Vec::new(); len + 1];
+        for (pc, instr) in self.instrs.iter().enumerate() {
+            if let Some(next) = instr.successor(pc, len) {
+                preds[next].push(pc);
+            }
+        }
+
+        let mut halts = vec![false; len + 1];
+        halts[len] = true;
+        let mut stack = vec![len];
+        while let Some(pc) = stack.pop() {
+            for &prev in &preds[pc] {
+                if !halts[prev] {
+                    halts[prev] = true;
+                    stack.push(prev);
+                }
+            }
+        }
+
+        let mut seen = vec![false; len];
+        let mut pc = 0;
+        while pc < len && !seen[pc] {
+            seen[pc] = true;
+            let before = self.instrs[pc];
+            if let Some(after) = before.flipped() {
+                if let Some(next) = after.successor(pc, len) {
+                    if halts[next] {
+                        return Some(Patch {
+                            index: pc,
+                            before,
+                            after,
+                        });
+                    }
+                }
+            }
+            pc = before.successor(pc, len)?;
+        }
+        None
+    }
+}
+
+/// How a run of the machine came to an end.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Outcome {
+    /// The program counter moved to just past the last instruction.
+    Halt,
+    /// An instruction was about to run for a second time.
+    Loop,
+    /// The program counter moved to `pc`, outside the program, after `steps`
+    /// instructions had run.
+    OutOfBounds { pc: isize, steps: usize },
+}
+
+#[derive(Clone, Debug)]
+pub struct Machine<'a> {
+    pub accum: isize,
+    pub pc: usize,
+    pub steps: usize,
+    instrs: &'a [Instr],
+    seen: Vec<bool>,
+}
+
+impl<'a> Machine<'a> {
+    pub fn new(program: &'a Program) -> Self {
+        let mut seen = vec![false; program.instrs.len()];
+        if let Some(first) = seen.first_mut() {
+            *first = true;
+        }
+        Machine {
+            accum: 0,
+            pc: 0,
+            steps: 0,
+            instrs: &program.instrs,
+            seen,
+        }
+    }
+
+    /// Executes the instruction at the program counter. Returns how the run
+    /// ended if that moved the program counter out of the program, which is
+    /// left unchanged if the new program counter is not a valid index.
+    pub fn step(&mut self) -> Option<Outcome> {
+        let Instr { opcode, arg } = match self.instrs.get(self.pc) {
+            Some(&instr) => instr,
+            None => return Some(Outcome::Halt),
+        };
+        let next = Instr { opcode, arg }.target(self.pc);
+        match usize::try_from(next) {
+            Ok(next) if next <= self.instrs.len() => {
+                (opcode.exec)(&mut self.accum, arg);
+                self.pc = next;
+                self.steps += 1;
+                if next == self.instrs.len() {
+                    Some(Outcome::Halt)
+                } else {
+                    None
+                }
+            }
+            _ => Some(Outcome::OutOfBounds {
+                pc: next,
+                steps: self.steps + 1,
+            }),
+        }
+    }
+
+    /// Executes one instruction like `step`, but also ends the run with
+    /// `Loop` if the next instruction has already run once.
+    pub fn advance(&mut self) -> Option<Outcome> {
+        if let Some(outcome) = self.step() {
+            return Some(outcome);
+        } else if self.seen[self.pc] {
+            return Some(Outcome::Loop);
+        }
+        self.seen[self.pc] = true;
+        None
+    }
+
+    /// Runs until the program halts, loops, or jumps out of bounds.
+    pub fn run(&mut self) -> Outcome {
+        loop {
+            if let Some(outcome) = self.advance() {
+                return outcome;
+            }
+        }
+    }
+
+    /// Returns the instruction at the program counter, if there is one.
+    pub fn instr(&self) -> Option<Instr> {
+        self.instrs.get(self.pc).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outcomes() {
+        let program = Program::parse("acc +1\njmp +1").unwrap();
+        assert_eq!(Machine::new(&program).run(), Outcome::Halt);
+
+        let program = Program::parse("acc +1\njmp -1").unwrap();
+        assert_eq!(Machine::new(&program).run(), Outcome::Loop);
+
+        let program = Program::parse("acc +1\njmp +2").unwrap();
+        let outcome = Outcome::OutOfBounds { pc: 3, steps: 2 };
+        assert_eq!(Machine::new(&program).run(), outcome);
+
+        let program = Program::parse("nop +0\nacc +1\njmp -4").unwrap();
+        let mut machine = Machine::new(&program);
+        let outcome = Outcome::OutOfBounds { pc: -2, steps: 3 };
+        assert_eq!(machine.run(), outcome);
+        assert_eq!((machine.pc, machine.accum), (2, 1));
+
+        let program = Program::parse("").unwrap();
+        assert_eq!(Machine::new(&program).run(), Outcome::Halt);
+    }
+
+    #[test]
+    fn repair() {
+        let program = Program::parse("nop +0\njmp +2\nacc +1\njmp -2").unwrap();
+        let patch = program.find_repair().unwrap();
+        assert_eq!(patch.to_string(), "3: jmp -2 -> nop -2");
+
+        let patched = program.patched(&patch);
+        assert_eq!(Machine::new(&patched).run(), Outcome::Halt);
+
+        let program = Program::parse("jmp +0\njmp +0").unwrap();
+        assert_eq!(program.find_repair(), None);
+    }
+
+    #[test]
+    fn extended_instruction_set() {
+        const DBL: Opcode = Opcode {
+            mnemonic: "dbl",
+            exec: |accum, _| *accum *= 2,
+            offset: |_| 1,
+        };
+
+        let instrs = InstructionSet::default().with(DBL);
+        let program = instrs.parse_program("acc +3\ndbl +0\nnop -7").unwrap();
+        assert_eq!(program.instrs[1].to_string(), "dbl +0");
+
+        let mut machine = Machine::new(&program);
+        assert_eq!(machine.run(), Outcome::Halt);
+        assert_eq!(machine.accum, 6);
+
+        let program = instrs.parse_program("dbl +0\njmp -1").unwrap();
+        let patch = program.find_repair().unwrap();
+        assert_eq!(patch.to_string(), "1: jmp -1 -> nop -1");
+
+        assert!(Program::parse("dbl +0").is_err());
+    }
+}
@@ -0,0 +1,206 @@
+//! Converting console programs to and from source text with symbolic labels.
+//!
+//! Source text has one instruction per line, optionally preceded by a label
+//! such as `top:`, which may also stand on a line of its own. An instruction's
+//! argument may name a label instead of giving a number, in which case it is
+//! the offset from the instruction to the label. Anything after a `;` is a
+//! comment.
+
+use super::{Instr, InstructionSet, Program, JMP};
+use crate::{Context, Result};
+use anyhow::{anyhow, bail, ensure};
+use std::collections::{BTreeMap, HashMap};
+
+fn is_label(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(ch) if ch.is_ascii_alphabetic() || ch == '_' => {}
+        _ => return false,
+    }
+    chars.all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+}
+
+/// Splits a line of source into its label, if any, and its instruction text.
+fn split_line(line: &str) -> (Option<&str>, &str) {
+    let line = line.split(';').next().unwrap_or_default().trim();
+    match line.find(':') {
+        Some(colon) => (Some(line[..colon].trim()), line[colon + 1..].trim()),
+        None => (None, line),
+    }
+}
+
+/// Assembles source text into a program made of opcodes from `instrs`.
+pub fn assemble(instrs: &InstructionSet, source: &str) -> Result<Program> {
+    let mut labels = HashMap::new();
+    let mut pc = 0isize;
+    for (number, line) in source.lines().enumerate() {
+        let (label, text) = split_line(line);
+        if let Some(label) = label {
+            ensure!(
+                is_label(label),
+                "line {}: invalid label {:?}",
+                number + 1,
+                label
+            );
+            ensure!(
+                labels.insert(label, pc).is_none(),
+                "line {}: duplicate label {:?}",
+                number + 1,
+                label
+            );
+        }
+        if !text.is_empty() {
+            pc += 1;
+        }
+    }
+
+    let mut program = Vec::new();
+    for (number, line) in source.lines().enumerate() {
+        let (_, text) = split_line(line);
+        if text.is_empty() {
+            continue;
+        }
+        let instr = assemble_instr(instrs, &labels, program.len() as isize, text)
+            .with_context(|| format!("line {}: {:?}", number + 1, text))?;
+        program.push(instr);
+    }
+    Ok(Program { instrs: program })
+}
+
+fn assemble_instr(
+    instrs: &InstructionSet,
+    labels: &HashMap<&str, isize>,
+    pc: isize,
+    text: &str,
+) -> Result<Instr> {
+    let mut split = text.split_whitespace();
+    let (mnemonic, arg) = match (split.next(), split.next(), split.next()) {
+        (Some(mnemonic), Some(arg), None) => (mnemonic, arg),
+        _ => bail!("expected an opcode and one argument"),
+    };
+    let opcode = instrs
+        .opcode(mnemonic)
+        .ok_or_else(|| anyhow!("unknown opcode {:?}", mnemonic))?;
+    let arg = if is_label(arg) {
+        match labels.get(arg) {
+            Some(target) => target - pc,
+            None => bail!("undefined label {:?}", arg),
+        }
+    } else {
+        arg.parse()?
+    };
+    Ok(Instr { opcode, arg })
+}
+
+/// Writes a program back out as source text, giving every `jmp` target within
+/// the program (or just past its end) a label of its own.
+pub fn disassemble(program: &Program) -> String {
+    let len = program.instrs.len();
+    let mut labels = BTreeMap::new();
+    for (pc, instr) in program.instrs.iter().enumerate() {
+        if instr.opcode == JMP {
+            if let Some(target) = instr.successor(pc, len) {
+                labels.insert(target, String::new());
+            }
+        }
+    }
+    for (number, name) in labels.values_mut().enumerate() {
+        *name = format!("l{}", number);
+    }
+
+    let mut source = String::new();
+    for pc in 0..=len {
+        if let Some(label) = labels.get(&pc) {
+            source.push_str(&format!("{}:\n", label));
+        }
+        let instr = match program.instrs.get(pc) {
+            Some(instr) => instr,
+            None => break,
+        };
+        let target = instr.successor(pc, len);
+        match target.and_then(|target| labels.get(&target)) {
+            Some(label) if instr.opcode == JMP => {
+                source.push_str(&format!("    {} {}\n", instr.opcode.mnemonic, label));
+            }
+            _ => source.push_str(&format!("    {}\n", instr)),
+        }
+    }
+    source
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Parse;
+    use indoc::indoc;
+
+    #[test]
+    fn assemble_labels() {
+        let program = assemble(
+            &InstructionSet::default(),
+            indoc! {"
+                start: nop +0   ; entry point
+                    acc +1
+                    jmp skip
+                back:
+                    acc +3
+                    jmp start
+                skip:
+                    jmp back
+                    jmp end
+                end:
+            "},
+        )
+        .unwrap();
+        let lines = program.instrs.iter().map(Instr::to_string);
+        assert_eq!(
+            lines.collect::<Vec<_>>(),
+            ["nop +0", "acc +1", "jmp +3", "acc +3", "jmp -4", "jmp -2", "jmp +1"]
+        );
+
+        let err = assemble(&InstructionSet::default(), "jmp nowhere").unwrap_err();
+        assert_eq!(err.to_string(), "line 1: \"jmp nowhere\"");
+        assert!(assemble(&InstructionSet::default(), "a: nop +0\na: nop +0").is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        let program = Program::parse(indoc! {"
+            nop +0
+            acc +1
+            jmp +4
+            acc +3
+            jmp -3
+            acc -99
+            acc +1
+            jmp -4
+            acc +6
+            jmp +1
+            jmp +5
+        "})
+        .unwrap();
+        let source = disassemble(&program);
+        assert_eq!(
+            source,
+            indoc! {"
+                    nop +0
+                l0:
+                    acc +1
+                    jmp l2
+                l1:
+                    acc +3
+                    jmp l0
+                    acc -99
+                l2:
+                    acc +1
+                    jmp l1
+                    acc +6
+                    jmp l3
+                l3:
+                    jmp +5
+            "}
+        );
+        let assembled = assemble(&InstructionSet::default(), &source).unwrap();
+        assert_eq!(assembled.instrs, program.instrs);
+    }
+}
@@ -0,0 +1,245 @@
+//! Control-flow graphs of console programs.
+
+use super::{Instr, Program};
+use crate::graph::Graph;
+use std::{collections::HashSet, fmt};
+
+/// Where control goes after the last instruction of a basic block.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Exit {
+    /// On to the start of another block, by index.
+    Block(usize),
+    /// Just past the end of the program, halting it.
+    Halt,
+    /// Somewhere else outside the program.
+    OutOfBounds(isize),
+}
+
+/// A run of instructions that always execute together, in order.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BasicBlock {
+    /// The index of the block's first instruction.
+    pub start: usize,
+    /// The index just past the block's last instruction.
+    pub end: usize,
+    pub exit: Exit,
+}
+
+#[derive(Clone, Debug)]
+pub struct ControlFlowGraph<'a> {
+    pub blocks: Vec<BasicBlock>,
+    instrs: &'a [Instr],
+    reachable: Vec<bool>,
+    loops: Vec<Vec<usize>>,
+}
+
+impl<'a> ControlFlowGraph<'a> {
+    /// Splits a program into basic blocks, starting a new block at the first
+    /// instruction, at every jump target, and after every jump.
+    pub fn new(program: &'a Program) -> Self {
+        let instrs = &program.instrs[..];
+        let len = instrs.len();
+
+        let mut leaders = vec![false; len + 1];
+        leaders[0] = true;
+        leaders[len] = true;
+        for pc in 0..len {
+            let next = instrs[pc].target(pc);
+            if next != pc as isize + 1 {
+                leaders[pc + 1] = true;
+                if (0..len as isize).contains(&next) {
+                    leaders[next as usize] = true;
+                }
+            }
+        }
+
+        let starts = (0..len).filter(|&pc| leaders[pc]).collect::<Vec<_>>();
+        let blocks = starts
+            .iter()
+            .map(|&start| {
+                let end = (start + 1..=len).find(|&pc| leaders[pc]).unwrap_or(len);
+                let next = instrs[end - 1].target(end - 1);
+                let exit = if next == len as isize {
+                    Exit::Halt
+                } else if (0..len as isize).contains(&next) {
+                    let index = starts.binary_search(&(next as usize));
+                    Exit::Block(index.expect("every jump target starts a block"))
+                } else {
+                    Exit::OutOfBounds(next)
+                };
+                BasicBlock { start, end, exit }
+            })
+            .collect::<Vec<_>>();
+
+        let mut graph = Graph::new();
+        for (index, block) in blocks.iter().enumerate() {
+            graph.add_node(index);
+            if let Exit::Block(next) = block.exit {
+                graph.add_edge(index, next, ());
+            }
+        }
+        let mut reachable = vec![false; blocks.len()];
+        if !blocks.is_empty() {
+            reachable[0] = true;
+            for &index in graph.descendants(&0) {
+                reachable[index] = true;
+            }
+        }
+        let loops = graph
+            .strongly_connected_components()
+            .into_iter()
+            .map(|component| {
+                let mut component = component.into_iter().copied().collect::<Vec<_>>();
+                component.sort_unstable();
+                component
+            })
+            .filter(|component| match component[..] {
+                [index] => blocks[index].exit == Exit::Block(index),
+                _ => true,
+            })
+            .collect();
+
+        ControlFlowGraph {
+            blocks,
+            instrs,
+            reachable,
+            loops,
+        }
+    }
+
+    /// Returns the index of the block containing the instruction at `pc`.
+    pub fn block_of(&self, pc: usize) -> Option<usize> {
+        match self.blocks.binary_search_by_key(&pc, |block| block.start) {
+            Ok(index) => Some(index),
+            Err(0) => None,
+            Err(index) if pc < self.blocks[index - 1].end => Some(index - 1),
+            Err(_) => None,
+        }
+    }
+
+    /// Returns whether control can ever reach the block at `index`.
+    pub fn is_reachable(&self, index: usize) -> bool {
+        self.reachable[index]
+    }
+
+    /// Returns the indices of blocks that control can never reach.
+    pub fn unreachable(&self) -> Vec<usize> {
+        (0..self.blocks.len())
+            .filter(|&index| !self.reachable[index])
+            .collect()
+    }
+
+    /// Returns the sets of blocks that control can cycle through forever,
+    /// each as a list of block indices in program order.
+    pub fn loops(&self) -> &[Vec<usize>] {
+        &self.loops
+    }
+
+    /// Renders the graph in the Graphviz DOT language, with one box per block
+    /// listing its instructions. Unreachable blocks are drawn dashed and blocks
+    /// on a loop are drawn bold.
+    pub fn to_dot(&self) -> String {
+        let looping = self.loops.iter().flatten().collect::<HashSet<_>>();
+        let mut dot = String::from("digraph cfg {\n");
+        dot.push_str("    node [shape=box, fontname=monospace];\n");
+        for (index, block) in self.blocks.iter().enumerate() {
+            let mut label = String::new();
+            for pc in block.start..block.end {
+                label.push_str(&format!("{}: {}\\l", pc, self.instrs[pc]));
+            }
+            let style = match (self.reachable[index], looping.contains(&index)) {
+                (false, _) => ", style=dashed",
+                (true, true) => ", style=bold",
+                (true, false) => "",
+            };
+            dot.push_str(&format!("    b{} [label=\"{}\"{}];\n", index, label, style));
+            match block.exit {
+                Exit::Block(next) => dot.push_str(&format!("    b{} -> b{};\n", index, next)),
+                Exit::Halt => dot.push_str(&format!("    b{} -> halt;\n", index)),
+                Exit::OutOfBounds(pc) => {
+                    dot.push_str(&format!(
+                        "    oob{} [label=\"{}\", shape=oval];\n",
+                        index, pc
+                    ));
+                    dot.push_str(&format!("    b{} -> oob{};\n", index, index));
+                }
+            }
+        }
+        dot.push_str("    halt [shape=oval];\n}\n");
+        dot
+    }
+}
+
+impl fmt::Display for ControlFlowGraph<'_> {
+    /// Lists each block's instruction range and exit, marking unreachable
+    /// blocks, followed by the loops.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, block) in self.blocks.iter().enumerate() {
+            let exit = match block.exit {
+                Exit::Block(next) => format!("b{}", next),
+                Exit::Halt => "halt".to_string(),
+                Exit::OutOfBounds(pc) => format!("out of bounds ({})", pc),
+            };
+            write!(f, "b{}: {}..{} -> {}", index, block.start, block.end, exit)?;
+            if !self.reachable[index] {
+                write!(f, " (unreachable)")?;
+            }
+            writeln!(f)?;
+        }
+        for blocks in &self.loops {
+            let blocks = blocks.iter().map(|index| format!("b{}", index));
+            writeln!(f, "loop: {}", blocks.collect::<Vec<_>>().join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Parse;
+    use indoc::indoc;
+
+    #[test]
+    fn blocks_and_loops() {
+        let program = Program::parse(indoc! {"
+            nop +0
+            acc +1
+            jmp +4
+            acc +3
+            jmp -3
+            acc -99
+            acc +1
+            jmp -4
+            acc +6
+        "})
+        .unwrap();
+        let cfg = ControlFlowGraph::new(&program);
+        assert_eq!(
+            cfg.to_string(),
+            indoc! {"
+                b0: 0..1 -> b1
+                b1: 1..3 -> b4
+                b2: 3..5 -> b1
+                b3: 5..6 -> b4 (unreachable)
+                b4: 6..8 -> b2
+                b5: 8..9 -> halt (unreachable)
+                loop: b1, b2, b4
+            "}
+        );
+        assert_eq!(cfg.block_of(4), Some(2));
+        assert_eq!(cfg.block_of(9), None);
+        assert_eq!(cfg.unreachable(), vec![3, 5]);
+        assert!(cfg
+            .to_dot()
+            .contains("b3 [label=\"5: acc -99\\l\", style=dashed];"));
+    }
+
+    #[test]
+    fn self_loops_and_escapes() {
+        let program = Program::parse("jmp +0\njmp -5").unwrap();
+        let cfg = ControlFlowGraph::new(&program);
+        assert_eq!(cfg.blocks[1].exit, Exit::OutOfBounds(-4));
+        assert_eq!(cfg.loops(), &[vec![0]]);
+    }
+}
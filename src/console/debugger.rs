@@ -0,0 +1,206 @@
+//! Breakpoints, watches and execution traces for the console machine.
+
+use super::{Instr, Machine, Outcome, Program};
+use std::{collections::BTreeSet, fmt};
+
+/// One executed instruction, with the accumulator as it was afterwards.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct TraceEntry {
+    pub pc: usize,
+    pub instr: Instr,
+    pub accum: isize,
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let instr = self.instr.to_string();
+        write!(f, "{:>5}  {:<8}  acc = {}", self.pc, instr, self.accum)
+    }
+}
+
+/// Why the debugger handed control back.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Stop {
+    /// A single step completed.
+    Stepped,
+    /// The program counter reached a breakpoint, or the target of `run_until`.
+    Breakpoint(usize),
+    /// A watched accumulator changed value.
+    Watch { old: isize, new: isize },
+    /// The machine stopped running for good.
+    Finished(Outcome),
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Stop::Stepped => write!(f, "stepped"),
+            Stop::Breakpoint(pc) => write!(f, "stopped at {}", pc),
+            Stop::Watch { old, new } => write!(f, "accumulator changed from {} to {}", old, new),
+            Stop::Finished(outcome) => write!(f, "finished: {:?}", outcome),
+        }
+    }
+}
+
+/// Drives a `Machine` one instruction at a time, recording everything it runs.
+#[derive(Clone, Debug)]
+pub struct Debugger<'a> {
+    machine: Machine<'a>,
+    breakpoints: BTreeSet<usize>,
+    watch_accum: bool,
+    trace: Vec<TraceEntry>,
+    finished: Option<Outcome>,
+}
+
+impl<'a> Debugger<'a> {
+    pub fn new(program: &'a Program) -> Self {
+        Debugger {
+            machine: Machine::new(program),
+            breakpoints: BTreeSet::new(),
+            watch_accum: false,
+            trace: Vec::new(),
+            finished: None,
+        }
+    }
+
+    pub fn machine(&self) -> &Machine<'a> {
+        &self.machine
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = usize> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    /// Adds a breakpoint, returning false if there already was one at `pc`.
+    pub fn add_breakpoint(&mut self, pc: usize) -> bool {
+        self.breakpoints.insert(pc)
+    }
+
+    /// Removes a breakpoint, returning false if there wasn't one at `pc`.
+    pub fn remove_breakpoint(&mut self, pc: usize) -> bool {
+        self.breakpoints.remove(&pc)
+    }
+
+    /// Sets whether to stop whenever the accumulator changes.
+    pub fn watch_accum(&mut self, watch: bool) {
+        self.watch_accum = watch;
+    }
+
+    pub fn is_watching_accum(&self) -> bool {
+        self.watch_accum
+    }
+
+    /// Every instruction executed so far, oldest first.
+    pub fn trace(&self) -> &[TraceEntry] {
+        &self.trace
+    }
+
+    /// Executes a single instruction.
+    pub fn step(&mut self) -> Stop {
+        if let Some(outcome) = self.finished {
+            return Stop::Finished(outcome);
+        }
+        let (pc, old) = (self.machine.pc, self.machine.accum);
+        let instr = match self.machine.instr() {
+            Some(instr) => instr,
+            None => return self.finish(Outcome::Halt),
+        };
+        let outcome = self.machine.advance();
+        let new = self.machine.accum;
+        self.trace.push(TraceEntry {
+            pc,
+            instr,
+            accum: new,
+        });
+        match outcome {
+            Some(outcome) => self.finish(outcome),
+            None if self.watch_accum && old != new => Stop::Watch { old, new },
+            None => Stop::Stepped,
+        }
+    }
+
+    fn finish(&mut self, outcome: Outcome) -> Stop {
+        self.finished = Some(outcome);
+        Stop::Finished(outcome)
+    }
+
+    /// Runs until a breakpoint or watch triggers, or the machine finishes.
+    pub fn resume(&mut self) -> Stop {
+        self.run_to(|_| false)
+    }
+
+    /// Runs until the program counter reaches `target`, or anything that
+    /// would stop `resume` happens first.
+    pub fn run_until(&mut self, target: usize) -> Stop {
+        self.run_to(|pc| pc == target)
+    }
+
+    fn run_to(&mut self, stop_at: impl Fn(usize) -> bool) -> Stop {
+        loop {
+            match self.step() {
+                Stop::Stepped => {}
+                stop => return stop,
+            }
+            let pc = self.machine.pc;
+            if stop_at(pc) || self.breakpoints.contains(&pc) {
+                return Stop::Breakpoint(pc);
+            }
+        }
+    }
+
+    /// Once the machine has finished with `Loop`, returns the instructions
+    /// that make up the loop, starting and ending just before the repeated one.
+    pub fn loop_report(&self) -> Option<&[TraceEntry]> {
+        if self.finished != Some(Outcome::Loop) {
+            return None;
+        }
+        let start = self
+            .trace
+            .iter()
+            .position(|entry| entry.pc == self.machine.pc)?;
+        Some(&self.trace[start..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Parse;
+
+    fn program() -> Program {
+        Program::parse("nop +0\nacc +1\njmp +4\nacc +3\njmp -3\nacc -99\nacc +1\njmp -4\nacc +6")
+            .unwrap()
+    }
+
+    #[test]
+    fn breakpoints_and_watches() {
+        let program = program();
+        let mut debugger = Debugger::new(&program);
+        debugger.add_breakpoint(6);
+        assert_eq!(debugger.resume(), Stop::Breakpoint(6));
+        assert_eq!(debugger.machine().accum, 1);
+
+        debugger.watch_accum(true);
+        assert_eq!(debugger.resume(), Stop::Watch { old: 1, new: 2 });
+        debugger.watch_accum(false);
+        assert_eq!(debugger.run_until(4), Stop::Breakpoint(4));
+        assert_eq!(debugger.machine().accum, 5);
+
+        assert_eq!(debugger.resume(), Stop::Finished(Outcome::Loop));
+        assert_eq!(debugger.step(), Stop::Finished(Outcome::Loop));
+    }
+
+    #[test]
+    fn loop_report() {
+        let program = program();
+        let mut debugger = Debugger::new(&program);
+        assert_eq!(debugger.loop_report(), None);
+        assert_eq!(debugger.resume(), Stop::Finished(Outcome::Loop));
+        assert_eq!(debugger.trace().len(), 7);
+
+        let cycle = debugger.loop_report().unwrap();
+        let pcs = cycle.iter().map(|entry| entry.pc).collect::<Vec<_>>();
+        assert_eq!(pcs, vec![1, 2, 6, 7, 3, 4]);
+        assert_eq!(cycle[0].to_string(), "    1  acc +1    acc = 1");
+    }
+}
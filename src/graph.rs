@@ -0,0 +1,461 @@
+//! A weighted directed graph keyed by arbitrary node values.
+
+use crate::Result;
+use anyhow::{anyhow, bail};
+use num::{CheckedAdd, CheckedMul, One};
+use std::{
+    borrow::Borrow,
+    collections::{HashMap, HashSet, VecDeque},
+    fmt::{Debug, Display},
+    hash::Hash,
+};
+
+/// A directed graph whose edges carry a weight of type `W`.
+///
+/// Nodes are identified by values of type `N` and stored once each, in the
+/// order they were first added. Both the outgoing and the incoming edges of
+/// every node are indexed, so walking the graph in either direction is cheap.
+#[derive(Clone, Debug)]
+pub struct Graph<N, W> {
+    nodes: Vec<N>,
+    ids: HashMap<N, usize>,
+    edges: Vec<Vec<(usize, W)>>,
+    reverse: Vec<Vec<(usize, W)>>,
+}
+
+impl<N, W> Default for Graph<N, W> {
+    fn default() -> Self {
+        Graph {
+            nodes: Vec::new(),
+            ids: HashMap::new(),
+            edges: Vec::new(),
+            reverse: Vec::new(),
+        }
+    }
+}
+
+impl<N, W> Graph<N, W>
+where
+    N: Clone + Eq + Hash,
+    W: Copy,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `node` to the graph if it isn't already present.
+    pub fn add_node(&mut self, node: N) {
+        self.id_or_insert(node);
+    }
+
+    /// Adds an edge from `from` to `to`, adding either node if necessary.
+    pub fn add_edge(&mut self, from: N, to: N, weight: W) {
+        let from = self.id_or_insert(from);
+        let to = self.id_or_insert(to);
+        self.edges[from].push((to, weight));
+        self.reverse[to].push((from, weight));
+    }
+
+    fn id_or_insert(&mut self, node: N) -> usize {
+        if let Some(&id) = self.ids.get(&node) {
+            return id;
+        }
+        let id = self.nodes.len();
+        self.nodes.push(node.clone());
+        self.ids.insert(node, id);
+        self.edges.push(Vec::new());
+        self.reverse.push(Vec::new());
+        id
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn contains<Q>(&self, node: &Q) -> bool
+    where
+        N: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.ids.contains_key(node)
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = &N> {
+        self.nodes.iter()
+    }
+
+    /// Returns the nodes that `node` has an edge to, with the edge weights.
+    pub fn successors<'a, Q>(&'a self, node: &Q) -> impl Iterator<Item = (&'a N, W)> + 'a
+    where
+        N: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.neighbours(&self.edges, node)
+    }
+
+    /// Returns the nodes that have an edge to `node`, with the edge weights.
+    pub fn predecessors<'a, Q>(&'a self, node: &Q) -> impl Iterator<Item = (&'a N, W)> + 'a
+    where
+        N: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.neighbours(&self.reverse, node)
+    }
+
+    fn neighbours<'a, Q>(
+        &'a self,
+        adjacency: &'a [Vec<(usize, W)>],
+        node: &Q,
+    ) -> impl Iterator<Item = (&'a N, W)> + 'a
+    where
+        N: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let edges = match self.ids.get(node) {
+            Some(&id) => &adjacency[id][..],
+            None => &[],
+        };
+        edges
+            .iter()
+            .map(move |&(id, weight)| (&self.nodes[id], weight))
+    }
+
+    /// Returns every node reachable from `node` by following edges forwards,
+    /// not including `node` itself unless it lies on a cycle.
+    pub fn descendants<Q>(&self, node: &Q) -> HashSet<&N>
+    where
+        N: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.reachable(&self.edges, node)
+    }
+
+    /// Returns every node from which `node` can be reached, not including
+    /// `node` itself unless it lies on a cycle.
+    pub fn ancestors<Q>(&self, node: &Q) -> HashSet<&N>
+    where
+        N: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.reachable(&self.reverse, node)
+    }
+
+    fn reachable<Q>(&self, adjacency: &[Vec<(usize, W)>], node: &Q) -> HashSet<&N>
+    where
+        N: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let seen = match self.ids.get(node) {
+            Some(&id) => self.reachable_ids(adjacency, id),
+            None => return HashSet::new(),
+        };
+        seen.iter()
+            .zip(&self.nodes)
+            .filter_map(|(&seen, node)| if seen { Some(node) } else { None })
+            .collect()
+    }
+
+    fn reachable_ids(&self, adjacency: &[Vec<(usize, W)>], start: usize) -> Vec<bool> {
+        let mut seen = vec![false; self.nodes.len()];
+        let mut queue = VecDeque::from(vec![start]);
+        while let Some(id) = queue.pop_front() {
+            for &(next, _) in &adjacency[id] {
+                if !seen[next] {
+                    seen[next] = true;
+                    queue.push_back(next);
+                }
+            }
+        }
+        seen
+    }
+
+    /// Orders the nodes so that every edge points from an earlier node to a
+    /// later one, or returns `None` if the graph contains a cycle.
+    pub fn topological_sort(&self) -> Option<Vec<&N>> {
+        let order = self.topological_ids()?;
+        Some(order.into_iter().map(|id| &self.nodes[id]).collect())
+    }
+
+    fn topological_ids(&self) -> Option<Vec<usize>> {
+        let mut in_degree = self.reverse.iter().map(Vec::len).collect::<Vec<_>>();
+        let mut queue = (0..self.nodes.len())
+            .filter(|&id| in_degree[id] == 0)
+            .collect::<VecDeque<_>>();
+        let mut order = Vec::with_capacity(self.nodes.len());
+        while let Some(id) = queue.pop_front() {
+            order.push(id);
+            for &(next, _) in &self.edges[id] {
+                in_degree[next] -= 1;
+                if in_degree[next] == 0 {
+                    queue.push_back(next);
+                }
+            }
+        }
+        if order.len() == self.nodes.len() {
+            Some(order)
+        } else {
+            None
+        }
+    }
+
+    /// Partitions the nodes into strongly connected components, each listed
+    /// in the order its nodes were added. Components come in topological
+    /// order: an edge between two components always points to a later one.
+    pub fn strongly_connected_components(&self) -> Vec<Vec<&N>> {
+        // Kosaraju's algorithm: order nodes by when a forward depth-first
+        // search finishes them, then collect components by searching the
+        // reverse edges in the opposite order.
+        let mut finished = Vec::with_capacity(self.nodes.len());
+        let mut seen = vec![false; self.nodes.len()];
+        for root in 0..self.nodes.len() {
+            if seen[root] {
+                continue;
+            }
+            seen[root] = true;
+            let mut path = vec![(root, 0)];
+            while let Some(&mut (id, ref mut edge)) = path.last_mut() {
+                match self.edges[id].get(*edge) {
+                    Some(&(next, _)) => {
+                        *edge += 1;
+                        if !seen[next] {
+                            seen[next] = true;
+                            path.push((next, 0));
+                        }
+                    }
+                    None => {
+                        finished.push(id);
+                        path.pop();
+                    }
+                }
+            }
+        }
+
+        let mut component = vec![None; self.nodes.len()];
+        let mut count = 0;
+        for &root in finished.iter().rev() {
+            if component[root].is_some() {
+                continue;
+            }
+            component[root] = Some(count);
+            let mut stack = vec![root];
+            while let Some(id) = stack.pop() {
+                for &(prev, _) in &self.reverse[id] {
+                    if component[prev].is_none() {
+                        component[prev] = Some(count);
+                        stack.push(prev);
+                    }
+                }
+            }
+            count += 1;
+        }
+
+        let mut components = vec![Vec::new(); count];
+        for (node, component) in self.nodes.iter().zip(component) {
+            if let Some(component) = component {
+                components[component].push(node);
+            }
+        }
+        components
+    }
+
+    /// Finds a cycle in the graph, if there is one, returning the nodes along
+    /// it with the first node repeated at the end.
+    pub fn find_cycle(&self) -> Option<Vec<&N>> {
+        #[derive(Copy, Clone, Eq, PartialEq)]
+        enum Visit {
+            New,
+            OnPath,
+            Done,
+        }
+
+        let mut visits = vec![Visit::New; self.nodes.len()];
+        for root in 0..self.nodes.len() {
+            if visits[root] != Visit::New {
+                continue;
+            }
+            visits[root] = Visit::OnPath;
+            let mut path = vec![(root, 0)];
+            while let Some(&(id, edge)) = path.last() {
+                let next = match self.edges[id].get(edge) {
+                    Some(&(next, _)) => next,
+                    None => {
+                        visits[id] = Visit::Done;
+                        path.pop();
+                        continue;
+                    }
+                };
+                if let Some(last) = path.last_mut() {
+                    last.1 += 1;
+                }
+                match visits[next] {
+                    Visit::New => {
+                        visits[next] = Visit::OnPath;
+                        path.push((next, 0));
+                    }
+                    Visit::OnPath => {
+                        let start = path.iter().position(|&(id, _)| id == next)?;
+                        let cycle = path[start..].iter().map(|&(id, _)| id);
+                        let cycle = cycle.chain(std::iter::once(next));
+                        return Some(cycle.map(|id| &self.nodes[id]).collect());
+                    }
+                    Visit::Done => {}
+                }
+            }
+        }
+        None
+    }
+
+    /// Computes the sum over all paths starting at `node` (including the
+    /// empty one) of the product of the path's edge weights.
+    ///
+    /// The count for each node below `node` is computed once from the counts
+    /// of its successors, so this runs in time linear in the size of the
+    /// graph. Fails if the graph contains a cycle, since there are then
+    /// infinitely many paths, or if a count overflows `W`.
+    pub fn weighted_path_count<Q>(&self, node: &Q) -> Result<W>
+    where
+        N: Borrow<Q> + Debug,
+        Q: Eq + Hash + ?Sized,
+        W: One + CheckedAdd + CheckedMul,
+    {
+        let start = match self.ids.get(node) {
+            Some(&start) => start,
+            None => bail!("node is not in the graph"),
+        };
+        let order = match self.topological_ids() {
+            Some(order) => order,
+            None => bail!(
+                "graph contains a cycle: {:?}",
+                self.find_cycle().unwrap_or_default()
+            ),
+        };
+        let mut below = self.reachable_ids(&self.edges, start);
+        below[start] = true;
+        let mut counts = vec![W::one(); self.nodes.len()];
+        for id in order.into_iter().rev().filter(|&id| below[id]) {
+            for &(next, weight) in &self.edges[id] {
+                counts[id] = weight
+                    .checked_mul(&counts[next])
+                    .and_then(|paths| counts[id].checked_add(&paths))
+                    .ok_or_else(|| anyhow!("path count overflowed at {:?}", self.nodes[id]))?;
+            }
+        }
+        Ok(counts[start])
+    }
+
+    /// Renders the graph in the Graphviz DOT language, labelling every edge
+    /// with its weight.
+    pub fn to_dot(&self, name: &str) -> String
+    where
+        N: Display,
+        W: Display,
+    {
+        fn quote(value: &dyn Display) -> String {
+            format!("\"{}\"", value.to_string().replace('"', "\\\""))
+        }
+
+        let mut dot = format!("digraph {} {{\n", quote(&name));
+        for (id, node) in self.nodes.iter().enumerate() {
+            dot.push_str(&format!("    {};\n", quote(node)));
+            for &(next, weight) in &self.edges[id] {
+                let next = quote(&self.nodes[next]);
+                dot.push_str(&format!(
+                    "    {} -> {} [label={}];\n",
+                    quote(node),
+                    next,
+                    weight
+                ));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diamond() -> Graph<&'static str, u32> {
+        let mut graph = Graph::new();
+        graph.add_edge("a", "b", 2);
+        graph.add_edge("a", "c", 3);
+        graph.add_edge("b", "d", 4);
+        graph.add_edge("c", "d", 5);
+        graph
+    }
+
+    #[test]
+    fn reachability() {
+        let graph = diamond();
+        let mut descendants = graph.descendants("a").into_iter().collect::<Vec<_>>();
+        descendants.sort();
+        assert_eq!(descendants, vec![&"b", &"c", &"d"]);
+        let mut ancestors = graph.ancestors("d").into_iter().collect::<Vec<_>>();
+        ancestors.sort();
+        assert_eq!(ancestors, vec![&"a", &"b", &"c"]);
+    }
+
+    #[test]
+    fn topological_sort() {
+        let mut graph = diamond();
+        let order = graph.topological_sort().unwrap();
+        assert_eq!(order.first(), Some(&&"a"));
+        assert_eq!(order.last(), Some(&&"d"));
+
+        graph.add_edge("d", "a", 1);
+        assert_eq!(graph.topological_sort(), None);
+        assert!(graph.weighted_path_count("a").is_err());
+    }
+
+    #[test]
+    fn strongly_connected_components() {
+        let mut graph = diamond();
+        graph.add_edge("d", "b", 1);
+        graph.add_edge("e", "e", 1);
+        let components = graph.strongly_connected_components();
+        assert_eq!(components.len(), 4);
+        assert!(components.contains(&vec![&"b", &"d"]));
+        assert!(components.contains(&vec![&"e"]));
+        let position = |node| components.iter().position(|c| c.contains(&node));
+        assert!(position(&"a") < position(&"c"));
+        assert!(position(&"c") < position(&"b"));
+    }
+
+    #[test]
+    fn find_cycle() {
+        let mut graph = diamond();
+        assert_eq!(graph.find_cycle(), None);
+
+        graph.add_edge("d", "b", 1);
+        assert_eq!(graph.find_cycle(), Some(vec![&"b", &"d", &"b"]));
+    }
+
+    #[test]
+    fn weighted_path_count() {
+        let graph = diamond();
+        assert_eq!(graph.weighted_path_count("d").unwrap(), 1);
+        assert_eq!(graph.weighted_path_count("b").unwrap(), 1 + 4);
+        assert_eq!(graph.weighted_path_count("a").unwrap(), 1 + 2 * 5 + 3 * 6);
+
+        let mut graph = Graph::<_, u8>::new();
+        graph.add_edge("a", "b", 16);
+        graph.add_edge("b", "c", 16);
+        assert_eq!(graph.weighted_path_count("b").unwrap(), 17);
+        assert!(graph.weighted_path_count("a").is_err());
+    }
+
+    #[test]
+    fn to_dot() {
+        let mut graph = Graph::new();
+        graph.add_edge("x", "say \"y\"", 7);
+        assert_eq!(
+            graph.to_dot("g"),
+            "digraph \"g\" {\n    \"x\";\n    \"x\" -> \"say \\\"y\\\"\" [label=7];\n    \"say \\\"y\\\"\";\n}\n"
+        );
+    }
+}
@@ -0,0 +1,205 @@
+//! Sets of values stored as sorted, non-overlapping half-open ranges.
+
+use std::{iter::FromIterator, ops::Range};
+
+/// A set of values of type `T`, stored as the fewest possible disjoint
+/// ranges, in ascending order. Ranges that overlap or touch are merged, and
+/// empty ranges are dropped.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct IntervalSet<T> {
+    ranges: Vec<Range<T>>,
+}
+
+impl<T> Default for IntervalSet<T> {
+    fn default() -> Self {
+        IntervalSet { ranges: Vec::new() }
+    }
+}
+
+impl<T: Copy + Ord> IntervalSet<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Returns the disjoint ranges making up the set, in ascending order.
+    pub fn iter(&self) -> std::slice::Iter<'_, Range<T>> {
+        self.ranges.iter()
+    }
+
+    /// Returns whether `value` lies in one of the set's ranges.
+    pub fn contains(&self, value: &T) -> bool {
+        let index = self.ranges.partition_point(|range| range.end <= *value);
+        match self.ranges.get(index) {
+            Some(range) => range.start <= *value,
+            None => false,
+        }
+    }
+
+    /// Adds every value in `range` to the set.
+    pub fn insert(&mut self, range: Range<T>) {
+        if range.start >= range.end {
+            return;
+        }
+        let first = self.ranges.partition_point(|r| r.end < range.start);
+        let last = self.ranges.partition_point(|r| r.start <= range.end);
+        let merged = if first == last {
+            range
+        } else {
+            let start = self.ranges[first].start.min(range.start);
+            start..self.ranges[last - 1].end.max(range.end)
+        };
+        self.ranges.splice(first..last, Some(merged));
+    }
+
+    /// Returns the values in either set.
+    pub fn union(&self, other: &Self) -> Self {
+        self.iter().chain(other.iter()).cloned().collect()
+    }
+
+    /// Returns the values in both sets.
+    pub fn intersection(&self, other: &Self) -> Self {
+        let mut ranges = Vec::new();
+        let (mut left, mut right) = (self.iter().peekable(), other.iter().peekable());
+        while let (Some(a), Some(b)) = (left.peek(), right.peek()) {
+            let start = a.start.max(b.start);
+            let end = a.end.min(b.end);
+            if start < end {
+                ranges.push(start..end);
+            }
+            if a.end <= b.end {
+                left.next();
+            } else {
+                right.next();
+            }
+        }
+        IntervalSet { ranges }
+    }
+
+    /// Returns the values in this set but not in `other`.
+    pub fn difference(&self, other: &Self) -> Self {
+        let mut ranges = Vec::new();
+        let mut others = other.iter().peekable();
+        for range in self.iter() {
+            let mut start = range.start;
+            while let Some(cut) = others.peek() {
+                if cut.end <= start {
+                    others.next();
+                    continue;
+                } else if cut.start >= range.end {
+                    break;
+                }
+                if start < cut.start {
+                    ranges.push(start..cut.start);
+                }
+                start = cut.end;
+                if cut.end > range.end {
+                    break;
+                }
+                others.next();
+            }
+            if start < range.end {
+                ranges.push(start..range.end);
+            }
+        }
+        IntervalSet { ranges }
+    }
+}
+
+impl<T: Copy + Ord> From<Range<T>> for IntervalSet<T> {
+    fn from(range: Range<T>) -> Self {
+        let mut set = IntervalSet::new();
+        set.insert(range);
+        set
+    }
+}
+
+impl<T: Copy + Ord> FromIterator<Range<T>> for IntervalSet<T> {
+    fn from_iter<I: IntoIterator<Item = Range<T>>>(iter: I) -> Self {
+        let mut ranges = iter
+            .into_iter()
+            .filter(|range| range.start < range.end)
+            .collect::<Vec<_>>();
+        ranges.sort_unstable_by_key(|range| range.start);
+        let mut merged: Vec<Range<T>> = Vec::with_capacity(ranges.len());
+        for range in ranges {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => merged.push(range),
+            }
+        }
+        IntervalSet { ranges: merged }
+    }
+}
+
+impl<T: Copy + Ord> Extend<Range<T>> for IntervalSet<T> {
+    fn extend<I: IntoIterator<Item = Range<T>>>(&mut self, iter: I) {
+        for range in iter {
+            self.insert(range);
+        }
+    }
+}
+
+impl<'a, T: Copy + Ord> IntoIterator for &'a IntervalSet<T> {
+    type Item = &'a Range<T>;
+    type IntoIter = std::slice::Iter<'a, Range<T>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranges(set: &IntervalSet<u32>) -> Vec<Range<u32>> {
+        set.iter().cloned().collect()
+    }
+
+    #[test]
+    fn merging() {
+        let mut set = vec![5..8, 1..3, 2..4, 10..10, 8..9]
+            .into_iter()
+            .collect::<IntervalSet<u32>>();
+        assert_eq!(ranges(&set), vec![1..4, 5..9]);
+
+        set.insert(12..15);
+        set.insert(4..5);
+        assert_eq!(ranges(&set), vec![1..9, 12..15]);
+        set.insert(0..20);
+        assert_eq!(ranges(&set), vec![0..20]);
+        set.insert(30..30);
+        assert_eq!(ranges(&set), vec![0..20]);
+    }
+
+    #[test]
+    fn contains() {
+        let set = vec![1..4, 6..7].into_iter().collect::<IntervalSet<u32>>();
+        let values = (0..8).filter(|value| set.contains(value));
+        assert_eq!(values.collect::<Vec<_>>(), vec![1, 2, 3, 6]);
+        assert!(!IntervalSet::new().contains(&0));
+    }
+
+    #[test]
+    fn set_operations() {
+        let a = vec![0..5, 10..15, 20..25]
+            .into_iter()
+            .collect::<IntervalSet<u32>>();
+        let b = vec![3..12, 14..21, 30..31]
+            .into_iter()
+            .collect::<IntervalSet<u32>>();
+        assert_eq!(ranges(&a.union(&b)), vec![0..25, 30..31]);
+        assert_eq!(
+            ranges(&a.intersection(&b)),
+            vec![3..5, 10..12, 14..15, 20..21]
+        );
+        assert_eq!(ranges(&a.difference(&b)), vec![0..3, 12..14, 21..25]);
+        assert_eq!(ranges(&b.difference(&a)), vec![5..10, 15..20, 30..31]);
+        assert!(a.difference(&a).is_empty());
+        assert_eq!(a.intersection(&IntervalSet::from(0..100)), a);
+    }
+}
@@ -4,6 +4,11 @@ use std::{fmt::Display, time::Instant};
 
 pub use anyhow::{Context, Error, Result};
 
+pub mod console;
+pub mod graph;
+pub mod interval;
+pub mod number_theory;
+
 pub trait Parse<'a>: Sized {
     fn parse<'b: 'a>(input_str: &'b str) -> Result<Self>;
 }
@@ -34,6 +39,17 @@ macro_rules! main {
             $crate::main_impl::<_, PartOne, PartTwo>(stringify!($day), input_str)
         }
     };
+    ($day:ident, cli = $cli:path) => {
+        fn main() -> $crate::Result<()> {
+            let input_str = $crate::input_str!($day);
+            let args = std::env::args().skip(1).collect::<Vec<_>>();
+            if args.is_empty() {
+                $crate::main_impl::<_, PartOne, PartTwo>(stringify!($day), input_str)
+            } else {
+                $cli(input_str, &args)
+            }
+        }
+    };
 }
 
 pub fn main_impl<'a, I, S1, S2>(day: &str, input_str: &'a str) -> Result<()>
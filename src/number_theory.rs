@@ -0,0 +1,339 @@
+//! Modular arithmetic: greatest common divisors, inverses and the Chinese
+//! remainder theorem.
+
+use crate::Result;
+use anyhow::{anyhow, ensure};
+use std::fmt;
+
+/// Returns `(g, x, y)` such that `g` is the greatest common divisor of `a`
+/// and `b`, `g >= 0`, and `a * x + b * y == g`.
+pub fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    let (mut old_r, mut r) = (a, b);
+    let (mut old_x, mut x) = (1, 0);
+    let (mut old_y, mut y) = (0, 1);
+    while r != 0 {
+        let q = old_r / r;
+        let next = (old_r - q * r, old_x - q * x, old_y - q * y);
+        old_r = r;
+        old_x = x;
+        old_y = y;
+        r = next.0;
+        x = next.1;
+        y = next.2;
+    }
+    if old_r < 0 {
+        (-old_r, -old_x, -old_y)
+    } else {
+        (old_r, old_x, old_y)
+    }
+}
+
+/// Returns the least common multiple of `a` and `b`, or `None` if it
+/// overflows.
+pub fn lcm(a: i128, b: i128) -> Option<i128> {
+    let (g, _, _) = extended_gcd(a, b);
+    if g == 0 {
+        return Some(0);
+    }
+    (a / g).checked_mul(b).map(i128::abs)
+}
+
+/// Returns the `x` in `0..m` such that `a * x ≡ 1 (mod m)`, if there is one.
+pub fn mod_inverse(a: i128, m: i128) -> Option<i128> {
+    if m <= 0 {
+        return None;
+    }
+    let (g, x, _) = extended_gcd(a.rem_euclid(m), m);
+    if g == 1 {
+        Some(x.rem_euclid(m))
+    } else {
+        None
+    }
+}
+
+/// Returns `a * b mod m` for `a` and `b` in `0..m`, without overflowing.
+fn mul_mod(a: i128, b: i128, m: i128) -> i128 {
+    if let Some(product) = a.checked_mul(b) {
+        return product % m;
+    }
+    let (mut a, mut b, m) = (a as u128, b as u128, m as u128);
+    let mut product = 0;
+    while b > 0 {
+        if b & 1 == 1 {
+            product = (product + a) % m;
+        }
+        a = (a << 1) % m;
+        b >>= 1;
+    }
+    product as i128
+}
+
+/// The set of integers `x` with `x ≡ residue (mod modulus)`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Congruence {
+    /// The least non-negative member of the set, always less than `modulus`.
+    pub residue: i128,
+    pub modulus: i128,
+}
+
+impl Congruence {
+    /// Creates the congruence `x ≡ residue (mod modulus)`, for any residue,
+    /// negative or not, and a positive modulus.
+    pub fn new(residue: i128, modulus: i128) -> Result<Self> {
+        ensure!(modulus > 0, "modulus {} is not positive", modulus);
+        Ok(Congruence {
+            residue: residue.rem_euclid(modulus),
+            modulus,
+        })
+    }
+
+    /// Returns whether `x` satisfies the congruence.
+    pub fn contains(&self, x: i128) -> bool {
+        x.rem_euclid(self.modulus) == self.residue
+    }
+
+    /// Returns the congruence satisfied by exactly the integers that satisfy
+    /// both `self` and `other`, or `None` if there are no such integers. The
+    /// moduli need not be coprime.
+    pub fn combine(&self, other: &Congruence) -> Result<Option<Congruence>> {
+        let (g, p, _) = extended_gcd(self.modulus, other.modulus);
+        let diff = other.residue - self.residue;
+        if diff % g != 0 {
+            return Ok(None);
+        }
+        let step = other.modulus / g;
+        let modulus = (self.modulus / g)
+            .checked_mul(other.modulus)
+            .ok_or_else(|| anyhow!("combined modulus of {} and {} overflows", self, other))?;
+        let k = mul_mod((diff / g).rem_euclid(step), p.rem_euclid(step), step);
+        let residue = self.residue + self.modulus * k;
+        Ok(Some(Congruence { residue, modulus }))
+    }
+}
+
+impl fmt::Display for Congruence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "x ≡ {} (mod {})", self.residue, self.modulus)
+    }
+}
+
+/// Solves a system of congruences with the Chinese remainder theorem,
+/// returning the single congruence equivalent to all of them, or `None` if
+/// they are inconsistent. An empty system is satisfied by every integer.
+pub fn crt<'a, I>(congruences: I) -> Result<Option<Congruence>>
+where
+    I: IntoIterator<Item = &'a Congruence>,
+{
+    Ok(explain_crt(congruences)?.solution)
+}
+
+/// A worked solution of a system of congruences, for showing how `crt`
+/// reached its answer.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CrtExplanation {
+    /// The system being solved.
+    pub system: Vec<Congruence>,
+    /// A description of where each congruence in the system came from, shown
+    /// next to it, or an empty string.
+    pub labels: Vec<String>,
+    /// The congruence equivalent to each prefix of the system, up to the
+    /// first one that has no solution.
+    pub steps: Vec<Congruence>,
+    pub solution: Option<Congruence>,
+}
+
+/// Solves a system of congruences like `crt`, recording every step.
+pub fn explain_crt<'a, I>(congruences: I) -> Result<CrtExplanation>
+where
+    I: IntoIterator<Item = &'a Congruence>,
+{
+    let system = congruences.into_iter().copied().collect::<Vec<_>>();
+    let mut steps = Vec::with_capacity(system.len());
+    let mut solution = Some(Congruence {
+        residue: 0,
+        modulus: 1,
+    });
+    for congruence in &system {
+        solution = match solution {
+            Some(solution) => solution.combine(congruence)?,
+            None => break,
+        };
+        steps.extend(solution);
+    }
+    Ok(CrtExplanation {
+        labels: vec![String::new(); system.len()],
+        system,
+        steps,
+        solution,
+    })
+}
+
+impl CrtExplanation {
+    /// Attaches a label to each congruence of the system, in order, so that
+    /// it can be traced back to whatever it was derived from.
+    pub fn labelled<I: IntoIterator<Item = String>>(mut self, labels: I) -> Self {
+        for (slot, label) in self.labels.iter_mut().zip(labels) {
+            *slot = label;
+        }
+        self
+    }
+
+    fn label(&self, index: usize) -> String {
+        match self.labels.get(index) {
+            Some(label) if !label.is_empty() => format!("  [{}]", label),
+            _ => String::new(),
+        }
+    }
+}
+
+impl fmt::Display for CrtExplanation {
+    /// Lists the system, the combined congruence after each step, and the
+    /// solution checked against every congruence in the system.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "system:")?;
+        for (index, congruence) in self.system.iter().enumerate() {
+            writeln!(f, "    {}{}", congruence, self.label(index))?;
+        }
+        writeln!(f, "steps:")?;
+        for (number, step) in self.steps.iter().enumerate() {
+            writeln!(f, "    {:>3}: {}", number + 1, step)?;
+        }
+        let solution = match self.solution {
+            Some(solution) => solution,
+            None => {
+                let index = self.steps.len();
+                let conflict = &self.system[index];
+                return writeln!(
+                    f,
+                    "no solution: {} is inconsistent{}",
+                    conflict,
+                    self.label(index)
+                );
+            }
+        };
+        writeln!(f, "solution: {}", solution)?;
+        writeln!(f, "verification of x = {}:", solution.residue)?;
+        for (index, congruence) in self.system.iter().enumerate() {
+            let remainder = solution.residue.rem_euclid(congruence.modulus);
+            let verdict = if remainder == congruence.residue {
+                "ok"
+            } else {
+                "FAILED"
+            };
+            writeln!(
+                f,
+                "    {} mod {} = {}  {}{}",
+                solution.residue,
+                congruence.modulus,
+                remainder,
+                verdict,
+                self.label(index)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+
+    #[test]
+    fn gcd_and_inverse() {
+        assert_eq!(extended_gcd(240, 46), (2, -9, 47));
+        assert_eq!(extended_gcd(-12, 18), (6, 1, 1));
+        assert_eq!(extended_gcd(0, 0), (0, 1, 0));
+
+        assert_eq!(lcm(4, 6), Some(12));
+        assert_eq!(lcm(-4, 6), Some(12));
+        assert_eq!(lcm(0, 6), Some(0));
+        assert_eq!(lcm(1 << 100, (3 << 30) + 1), None);
+
+        assert_eq!(mod_inverse(3, 11), Some(4));
+        assert_eq!(mod_inverse(-3, 11), Some(7));
+        assert_eq!(mod_inverse(6, 9), None);
+        assert_eq!(mod_inverse(1, 1), Some(0));
+    }
+
+    #[test]
+    fn chinese_remainders() {
+        let system = [(2, 3), (3, 5), (2, 7)]
+            .iter()
+            .map(|&(residue, modulus)| Congruence::new(residue, modulus).unwrap())
+            .collect::<Vec<_>>();
+        let solution = crt(&system).unwrap().unwrap();
+        assert_eq!(solution, Congruence::new(23, 105).unwrap());
+        assert!(system.iter().all(|c| c.contains(solution.residue)));
+
+        let a = Congruence::new(3, 4).unwrap();
+        let b = Congruence::new(1, 6).unwrap();
+        assert_eq!(
+            a.combine(&b).unwrap(),
+            Some(Congruence::new(7, 12).unwrap())
+        );
+        let c = Congruence::new(2, 6).unwrap();
+        assert_eq!(a.combine(&c).unwrap(), None);
+
+        assert_eq!(crt(&[]).unwrap(), Some(Congruence::new(0, 1).unwrap()));
+        assert_eq!(crt(&[a, b, c]).unwrap(), None);
+        assert!(Congruence::new(1, 0).is_err());
+    }
+
+    #[test]
+    fn explanation() {
+        let system = [
+            Congruence::new(2, 3).unwrap(),
+            Congruence::new(3, 5).unwrap(),
+        ];
+        assert_eq!(
+            explain_crt(&system).unwrap().to_string(),
+            indoc! {"
+                system:
+                    x ≡ 2 (mod 3)
+                    x ≡ 3 (mod 5)
+                steps:
+                      1: x ≡ 2 (mod 3)
+                      2: x ≡ 8 (mod 15)
+                solution: x ≡ 8 (mod 15)
+                verification of x = 8:
+                    8 mod 3 = 2  ok
+                    8 mod 5 = 3  ok
+            "}
+        );
+
+        let system = [
+            Congruence::new(3, 4).unwrap(),
+            Congruence::new(2, 6).unwrap(),
+            Congruence::new(1, 5).unwrap(),
+        ];
+        let labels = vec!["a".to_string(), "b".to_string()];
+        let explanation = explain_crt(&system).unwrap().labelled(labels);
+        assert_eq!(explanation.steps.len(), 1);
+        assert_eq!(
+            explanation.to_string(),
+            indoc! {"
+                system:
+                    x ≡ 3 (mod 4)  [a]
+                    x ≡ 2 (mod 6)  [b]
+                    x ≡ 1 (mod 5)
+                steps:
+                      1: x ≡ 3 (mod 4)
+                no solution: x ≡ 2 (mod 6) is inconsistent  [b]
+            "}
+        );
+    }
+
+    #[test]
+    fn large_moduli() {
+        let big = (1 << 100) + 277;
+        let a = Congruence::new(-1, big).unwrap();
+        let b = Congruence::new(5, 1 << 20).unwrap();
+        let solution = a.combine(&b).unwrap().unwrap();
+        assert!(a.contains(solution.residue) && b.contains(solution.residue));
+        assert_eq!(b.combine(&a).unwrap(), Some(solution));
+
+        let c = Congruence::new(solution.residue, 1 << 30).unwrap();
+        assert!(solution.combine(&c).is_err());
+    }
+}